@@ -0,0 +1 @@
+init.lua
\ No newline at end of file
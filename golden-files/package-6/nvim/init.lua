@@ -0,0 +1 @@
+lua
@@ -1,13 +1,76 @@
-use crate::{linker, package, path, Error};
+use crate::{
+    event, ignore, linker, package, path, plan, state, status, template, variant,
+    CreateHardlinkError, Error, ReadFileError,
+};
+
+/// Directory, relative to the target directory, where conflicting files are
+/// moved to when using the backup conflict policy.
+pub const BACKUP_DIRECTORY: &str = ".stow-backup";
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub conflict_policy: plan::ConflictPolicy,
+    /// Move the files backed up while stowing back in place when unstowing.
+    pub restore_backups: bool,
+    /// Create symlinks targeting absolute paths instead of paths relative to
+    /// the directory containing them.
+    pub absolute: bool,
+    /// Always create the package directories in the target directory instead
+    /// of linking the ones owned by a single package.
+    pub no_folding: bool,
+    /// Patterns of the files to ignore in every package, on top of the ones
+    /// defined by each package.
+    pub ignore: ignore::Ignore,
+    /// Link the package files and directories starting with `dot-` as hidden
+    /// ones, e.g. `dot-zshrc` as `.zshrc`.
+    pub dotfiles: bool,
+    /// Machine the packages are stowed on, selecting which variant of the
+    /// package files to use.
+    pub machine: variant::Machine,
+    /// Values of the placeholders of the templates.
+    pub variables: template::Variables,
+    /// Copy every package file instead of linking it.
+    pub copy: bool,
+    /// Create hardlinks instead of symlinks.
+    pub hardlink: bool,
+    /// How the actions, warnings and conflicts are reported.
+    pub format: event::Format,
+    /// The linker leaves the target directory untouched, so the actions are
+    /// reported as planned rather than done.
+    pub dry_run: bool,
+}
 
 pub struct Command<'a, W: std::io::Write, L: linker::Linker + ?Sized> {
     logger: W,
     linker: &'a mut L,
+    options: Options,
+    state: state::State,
 }
 
 impl<'a, W: std::io::Write, L: linker::Linker + ?Sized> Command<'a, W, L> {
     pub fn new(logger: W, linker: &'a mut L) -> Self {
-        Self { logger, linker }
+        Self {
+            logger,
+            linker,
+            options: Options::default(),
+            state: state::State::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Uses `state` to know which links have been created by the previous
+    /// runs, and records in it the links created or removed.
+    pub fn with_state(mut self, state: state::State) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn state(&self) -> &state::State {
+        &self.state
     }
 
     pub fn stow(
@@ -16,215 +79,3818 @@ impl<'a, W: std::io::Write, L: linker::Linker + ?Sized> Command<'a, W, L> {
         root_dest: &path::Destination,
         packages: Vec<String>,
     ) -> Result<(), Error> {
+        let plan = self.plan_stow(root_src, root_dest, &packages)?;
+        self.execute(&plan)
+    }
+
+    pub fn unstow(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: Vec<String>,
+    ) -> Result<(), Error> {
+        let plan = self.plan_unstow(root_src, root_dest, &packages)?;
+        self.execute(&plan)
+    }
+
+    /// Removes the links pointing to files which are not part of the packages
+    /// anymore and links the current content of the packages, as a single plan.
+    pub fn restow(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: Vec<String>,
+    ) -> Result<(), Error> {
+        let plan = self.plan_restow(root_src, root_dest, &packages)?;
+        self.execute(&plan)
+    }
+
+    /// Applies a profile to the target directory as a single plan: the
+    /// packages of the profile previously applied which are not part of the
+    /// new one anymore are unstowed, and the packages of the new profile are
+    /// restowed. The applied profile is recorded in the state.
+    pub fn apply_profile(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        profile: &str,
+        packages: Vec<String>,
+    ) -> Result<(), Error> {
+        let removed: Vec<String> = match self.state.profile(root_dest.as_ref()) {
+            Some(previous) => previous
+                .packages
+                .iter()
+                .filter(|p| !packages.contains(p))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let plan = self.plan_switch(root_src, root_dest, &removed, &packages)?;
+        self.execute(&plan)?;
+        self.state.apply(root_dest.as_ref(), profile, &packages);
+
+        Ok(())
+    }
+
+    /// Unstows the packages of a profile, along with the ones of the profile
+    /// applied to the target directory, and forgets about the applied profile.
+    pub fn remove_profile(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        mut packages: Vec<String>,
+    ) -> Result<(), Error> {
+        if let Some(previous) = self.state.profile(root_dest.as_ref()) {
+            for p in previous.packages.iter() {
+                if !packages.contains(p) {
+                    packages.push(p.clone());
+                }
+            }
+        }
+
+        self.unstow(root_src, root_dest, packages)?;
+        self.state.forget_profile(root_dest.as_ref());
+
+        Ok(())
+    }
+
+    /// Runs `run` for each package on its own, reporting the outcome of each
+    /// of them instead of stopping at the first failure.
+    pub fn for_each_package<F>(&mut self, packages: Vec<String>, mut run: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self, Vec<String>) -> Result<(), Error>,
+    {
+        let mut failures = 0;
+        for p in packages.into_iter() {
+            let result = run(self, vec![p.clone()]);
+            if result.is_err() {
+                failures += 1;
+            }
+
+            match (self.options.format, result) {
+                (event::Format::Text, Ok(())) => writeln!(self.logger, "package: {} ok", p),
+                (event::Format::Text, Err(err)) => {
+                    writeln!(self.logger, "package: {} failed: {}", p, err)
+                }
+                (event::Format::Json, Ok(())) => writeln!(
+                    self.logger,
+                    "{}",
+                    event::Event::new(event::Kind::Package)
+                        .with_package(&p)
+                        .with_outcome("ok")
+                ),
+                (event::Format::Json, Err(err)) => writeln!(
+                    self.logger,
+                    "{}",
+                    event::Event::new(event::Kind::Package)
+                        .with_package(&p)
+                        .with_outcome("failed")
+                        .with_message(&err.to_string())
+                ),
+            }
+            .map_err(|e| Error::Generic(format!("failed to print package outcome: {}", e)))?;
+        }
+
+        if failures > 0 {
+            return Err(Error::FailedPackages(failures));
+        }
+
+        Ok(())
+    }
+
+    /// Walks all the packages and computes every action required to stow them
+    /// without modifying the target directory.
+    pub fn plan_stow(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: &[String],
+    ) -> Result<plan::Plan, Error> {
+        let mut plan = plan::Plan::default();
+        let contributors = self.count_contributors(root_src, root_dest, packages)?;
+        for p in packages.iter() {
+            self.plan_stow_package(&mut plan, root_src, root_dest, p, &contributors)?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Computes every action required to remove the links of the packages
+    /// without modifying the target directory.
+    pub fn plan_unstow(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: &[String],
+    ) -> Result<plan::Plan, Error> {
+        let mut plan = plan::Plan::default();
         for p in packages.iter() {
-            let package = package::Package::new(root_src, p)?;
-            for file in package.read_files()? {
-                let file = file?;
-                let file_src_path = root_src.join(p).join(&file);
-                let file_dest_path = root_dest.join(&file);
+            self.plan_unstow_package(&mut plan, root_src, root_dest, p, false)?;
+        }
+        self.plan_remove_empty_directories(&mut plan, root_src, root_dest)?;
 
-                let parent_directory = file_dest_path
-                    .parent()
-                    .ok_or_else(|| Error::ParentDirectory(file_dest_path.display().to_string()))?;
+        Ok(plan)
+    }
+
+    pub fn plan_restow(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: &[String],
+    ) -> Result<plan::Plan, Error> {
+        self.plan_switch(root_src, root_dest, &[], packages)
+    }
+
+    /// Computes the actions required to unstow the `removed` packages and to
+    /// restow the `packages`, so a package can replace the files of another.
+    pub fn plan_switch(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        removed: &[String],
+        packages: &[String],
+    ) -> Result<plan::Plan, Error> {
+        let mut plan = plan::Plan::default();
+        for p in removed.iter() {
+            self.plan_unstow_package(&mut plan, root_src, root_dest, p, false)?;
+        }
+        for p in packages.iter() {
+            self.plan_unstow_package(&mut plan, root_src, root_dest, p, true)?;
+        }
+        self.plan_remove_empty_directories(&mut plan, root_src, root_dest)?;
+        let contributors = self.count_contributors(root_src, root_dest, packages)?;
+        for p in packages.iter() {
+            self.plan_stow_package(&mut plan, root_src, root_dest, p, &contributors)?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Removes the symlinks of the target directory pointing inside the stow
+    /// directory to files which don't exist anymore.
+    pub fn prune(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        depth: usize,
+    ) -> Result<(), Error> {
+        let plan = self.plan_prune(root_src, root_dest, depth)?;
+        self.execute(&plan)
+    }
 
-                if !self.linker.directory_exists(parent_directory)? {
-                    self.linker.create_directory(parent_directory)?;
+    /// Computes the removal of the dangling symlinks to the stow directory,
+    /// looking at most `depth` directories below the target directory. The
+    /// stow directory itself is never scanned.
+    pub fn plan_prune(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        depth: usize,
+    ) -> Result<plan::Plan, Error> {
+        let mut plan = plan::Plan::default();
+        let root = self.linker.canonicalize(root_src.as_ref())?;
+        let mut directories = std::collections::VecDeque::new();
+        directories.push_back((root_dest.as_ref().to_path_buf(), 0));
+
+        while let Some((directory, level)) = directories.pop_front() {
+            let links = match self.linker.list_symlinks(&directory) {
+                Ok(links) => links,
+                Err(err) if level > 0 => {
+                    self.skip_unreadable(&directory, err)?;
+                    continue;
                 }
+                Err(err) => return Err(err),
+            };
 
-                let src = file_src_path.as_path();
-                let dest = file_dest_path.as_path();
+            for link in links {
+                let target = self.linker.read_link(&link)?;
+                let target = self.resolve_symlink(&plan, &link, &target);
+                let package = match target.strip_prefix(&root) {
+                    Ok(file) => file
+                        .components()
+                        .next()
+                        .and_then(|c| c.as_os_str().to_str())
+                        .unwrap_or_default()
+                        .to_string(),
+                    Err(_) => continue,
+                };
 
-                if let Ok(current_src) = self.linker.read_link(dest) {
-                    if current_src.as_path().eq(src) {
-                        continue;
-                    }
+                if self.exists(&target) {
+                    continue;
+                }
 
+                if self.options.format == event::Format::Text {
                     writeln!(
                         self.logger,
-                        "warning: override symlink {} from {} to {}",
-                        dest.display(),
-                        current_src.display(),
-                        src.display()
+                        "prune: {} (broken link to {})",
+                        link.display(),
+                        target.display()
                     )
-                    .map_err(|e| Error::Generic(format!("failed to print warning: {}", e)))?;
+                    .map_err(|e| Error::Generic(format!("failed to print prune: {}", e)))?;
                 }
+                plan.push(&package, plan::Operation::DeleteFile(link));
+            }
+
+            if level >= depth {
+                continue;
+            }
 
-                if self.linker.file_exists(dest)? {
-                    writeln!(self.logger, "warning: delete file {}", dest.display(),)
-                        .map_err(|e| Error::Generic(format!("failed to print warning: {}", e)))?;
-                    self.linker.delete_file(dest)?;
+            let subdirectories = match self.linker.list_directories(&directory) {
+                Ok(subdirectories) => subdirectories,
+                Err(err) if level > 0 => {
+                    self.skip_unreadable(&directory, err)?;
+                    continue;
                 }
+                Err(err) => return Err(err),
+            };
 
-                self.linker.create_symlink(&src.into(), &dest.into())?
+            for subdirectory in subdirectories {
+                if self
+                    .canonical_directory(&plan, &subdirectory)
+                    .starts_with(&root)
+                {
+                    continue;
+                }
+                directories.push_back((subdirectory, level + 1));
             }
         }
 
-        Ok(())
+        Ok(plan)
     }
 
-    pub fn unstow(
+    /// Warns about a directory below the target directory which cannot be
+    /// scanned, e.g. one owned by another user, instead of failing.
+    fn skip_unreadable(&mut self, directory: &std::path::Path, err: Error) -> Result<(), Error> {
+        let reason = match err {
+            Error::ReadFile(err) => err.reason,
+            err => err.to_string(),
+        };
+        self.warn(
+            None,
+            directory,
+            "skipped",
+            format_args!("skip {}: {}", directory.display(), reason),
+        )
+    }
+
+    /// Inspects the target directory, without modifying it, to tell the state
+    /// of the link of each file of the packages.
+    pub fn status(
         &mut self,
         root_src: &path::Source,
         root_dest: &path::Destination,
-        packages: Vec<String>,
-    ) -> Result<(), Error> {
+        packages: &[String],
+    ) -> Result<status::Report, Error> {
+        let mut report = status::Report {
+            packages: packages.to_vec(),
+            ..Default::default()
+        };
+
         for p in packages.iter() {
-            let package = package::Package::new(root_src, p)?;
-            for dir in package.read_dirs()? {
-                let dir = dir?;
-                let dir_src_path = root_src.join(p).join(&dir);
-                let dir_dest_path = root_dest.join(&dir);
-                if !self.linker.directory_exists(&dir_dest_path)? {
+            let package = self.package(root_src, p)?;
+            let package_src = self
+                .linker
+                .canonicalize(root_src.as_ref())?
+                .join(package.name());
+            for file in self.entries(&package)? {
+                if package.is_directory(&file) {
                     continue;
                 }
 
-                for destination_file in self.linker.list_symlinks(&dir_dest_path)? {
-                    let target_link = self.linker.read_link(&destination_file)?;
-                    let target_link = target_link
-                        .parent()
-                        .ok_or_else(|| {
-                            Error::Generic(format!("cannot get {} dirname", target_link.display(),))
-                        })
-                        .and_then(|p| self.linker.canonicalize(p))?;
+                let state = self.file_state(root_src, root_dest, &package_src, &file)?;
+                report.entries.push(status::Entry {
+                    package: p.to_string(),
+                    destination: self.destination(root_dest, &file),
+                    state,
+                });
+            }
+        }
+
+        Ok(report)
+    }
 
-                    let dir_src_path = self.linker.canonicalize(&dir_src_path)?;
+    /// Tells whether anything exists at `file`, following symlinks. Linkers
+    /// report an error when checking a file of the wrong type, which still
+    /// means it exists.
+    fn exists(&mut self, file: &std::path::Path) -> bool {
+        !matches!(self.linker.file_exists(file), Ok(false))
+            || !matches!(self.linker.directory_exists(file), Ok(false))
+    }
 
-                    if target_link == dir_src_path {
-                        self.linker.delete_file(&destination_file)?;
-                    }
+    fn file_state(
+        &mut self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        package_src: &std::path::Path,
+        file: &str,
+    ) -> Result<status::State, Error> {
+        let plan = plan::Plan::default();
+        // the file may be linked through a folded directory
+        let mut directory = std::path::PathBuf::new();
+        for component in std::path::Path::new(file).components() {
+            directory.push(component);
+            let rel = directory
+                .to_str()
+                .ok_or_else(|| Error::Generic(format!("cannot convert path {} to string", file)))?;
+            let destination = self.destination(root_dest, rel);
+            if !self.linker.is_symlink(&destination) {
+                continue;
+            }
+
+            let link = self.linker.read_link(&destination)?;
+            let target = self.resolve_symlink(&plan, &destination, &link);
+            if target == package_src.join(rel) {
+                return Ok(status::State::Linked);
+            }
+
+            if rel != file {
+                if !self.is_stowed(&plan, root_src, &destination, &link) {
+                    continue;
                 }
+
+                let rest = std::path::Path::new(file)
+                    .strip_prefix(rel)
+                    .map_err(|e| Error::Generic(format!("cannot inspect {}: {}", file, e)))?;
+                return Ok(status::State::Elsewhere(target.join(rest)));
             }
+
+            return Ok(if self.exists(&target) {
+                status::State::Elsewhere(target)
+            } else {
+                status::State::Broken(target)
+            });
         }
-        Ok(())
+
+        let destination = self.destination(root_dest, file);
+        if self
+            .linker
+            .is_same_file(&destination, &package_src.join(file))?
+        {
+            return Ok(status::State::Linked);
+        }
+
+        if matches!(self.linker.file_exists(&destination), Ok(true))
+            && self.is_written(&destination)?
+        {
+            return Ok(status::State::Linked);
+        }
+
+        if self.exists(&destination) {
+            return Ok(status::State::Blocked);
+        }
+
+        Ok(status::State::Missing)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::linker;
+    fn plan_stow_package(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        p: &str,
+        contributors: &std::collections::HashMap<std::path::PathBuf, usize>,
+    ) -> Result<(), Error> {
+        let package = self.package(root_src, p)?;
+        let p = package.name();
+        let package_src = self.linker.canonicalize(root_src.as_ref())?.join(p);
+        if self.options.hardlink
+            && !self
+                .linker
+                .is_same_filesystem(&package_src, root_dest.as_ref())?
+        {
+            return Err(Error::CreateHardlink(CreateHardlinkError {
+                source: package_src.display().to_string(),
+                destination: root_dest.to_string(),
+                reason: linker::CROSS_DEVICE_REASON.to_string(),
+            }));
+        }
 
-    #[test]
-    fn stow_packages() {
-        let mut commands_output = std::io::BufWriter::new(Vec::new());
-        let mut warnings_output = std::io::BufWriter::new(Vec::new());
-        let mut linker = Box::new(linker::Verbose::new(
-            &mut commands_output,
-            linker::Noop::default(),
-        ));
+        let mut skipped_directories: Vec<std::path::PathBuf> = Vec::new();
+        let all_entries = package
+            .read_entries()?
+            .collect::<Result<Vec<String>, Error>>()?;
+        let entries = variant::select(all_entries.clone(), &self.options.machine);
 
-        let src: path::Source = "golden-files".into();
-        let dest: path::Destination = "/home/jdoe".into();
+        for file in entries.iter() {
+            if skipped_directories
+                .iter()
+                .any(|directory| std::path::Path::new(&file).starts_with(directory))
+            {
+                continue;
+            }
 
-        Command::new(&mut warnings_output, linker.as_mut())
-            .stow(
-                &src,
-                &dest,
-                vec!["package-1".to_string(), "package-2".to_string()],
-            )
-            .expect("shouldn't fail");
+            let src = package_src.join(file);
+            let dest = self.destination(root_dest, file);
 
-        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
-        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+            let parent_directory = dest
+                .parent()
+                .ok_or_else(|| Error::ParentDirectory(dest.display().to_string()))?;
 
-        let expected_output = vec![
-            "mkdir -p /home/jdoe",
-            "readlink /home/jdoe/file-1",
-            "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
-            "readlink /home/jdoe/file-2",
-            "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
-            "mkdir -p /home/jdoe/subdirectory",
-            "readlink /home/jdoe/subdirectory/file-2",
-            "ln -s golden-files/package-2/subdirectory/file-2 /home/jdoe/subdirectory/file-2",
-            "readlink /home/jdoe/file-1",
-            "rm /home/jdoe/file-1",
-            "ln -s golden-files/package-2/file-1 /home/jdoe/file-1",
-        ];
+            if !self.plan_directory(plan, p, parent_directory)? {
+                skipped_directories.push(file.into());
+                continue;
+            }
 
-        let actual_output = output.trim().split('\n').collect::<Vec<&str>>();
-        assert_eq!(expected_output.len(), actual_output.len());
-        assert!(find_subset(&actual_output, &expected_output[0..3]));
-        assert!(find_subset(&actual_output, &expected_output[3..5]));
-        assert!(find_subset(&actual_output, &expected_output[3..5]));
-        assert!(find_subset(&actual_output, &expected_output[5..8]));
-        assert!(find_subset(&actual_output, &expected_output[8..11]));
+            if package.is_directory(file) {
+                let foldable = !self.options.no_folding
+                    && !self.options.hardlink
+                    && contributors.get(&dest).copied().unwrap_or(0) <= 1
+                    && !has_renamed_files(&all_entries, file, self.options.dotfiles)
+                    && !all_entries.iter().any(|entry| {
+                        std::path::Path::new(entry).starts_with(file)
+                            && self.is_copied(&package, entry)
+                    });
+                if !self.plan_stow_directory(plan, root_src, p, &src, &dest, foldable)? {
+                    skipped_directories.push(file.into());
+                }
+                continue;
+            }
 
-        assert_eq!(vec![
-            "warning: override symlink /home/jdoe/file-1 from golden-files/package-1/file-1 to golden-files/package-2/file-1",
-            "warning: delete file /home/jdoe/file-1",
-        ], warning.trim().split('\n').collect::<Vec<&str>>());
-    }
+            if template::is_template(&variant::base(file)) || self.is_copied(&package, file) {
+                self.plan_write(plan, root_src, root_dest, &package, file, &src)?;
+                continue;
+            }
 
-    #[test]
-    fn unstow_packages() {
-        let mut commands_output = std::io::BufWriter::new(Vec::new());
-        let mut warnings_output = std::io::BufWriter::new(Vec::new());
-        let mut noop = linker::Noop::default();
-        noop.directories = vec!["/home/jdoe".into(), "/home/jdoe/subdirectory".into()];
-        noop.files = vec![
-            (
-                "/home/jdoe/file-other".into(),
-                "golden-files/package-3/file-other".into(),
-            ),
-            (
-                "/home/jdoe/subdirectory/file-2".into(),
-                "golden-files/package-2/subdirectory/file-2".into(),
-            ),
-            (
-                "/home/jdoe/file-1".into(),
-                "golden-files/package-1/file-1".into(),
-            ),
-            (
-                "/home/jdoe/file-2".into(),
-                "golden-files/package-2/file-2".into(),
-            ),
-        ];
-        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+            let link_target = self.link_target(plan, parent_directory, &src);
 
-        let src: path::Source = "golden-files".into();
-        let dest: path::Destination = "/home/jdoe".into();
+            match self.inspect(plan, &dest)? {
+                plan::Entry::Missing => {}
+                plan::Entry::Symlink(current_src)
+                    if self.resolve_symlink(plan, &dest, &current_src) == src =>
+                {
+                    if !self.options.hardlink {
+                        continue;
+                    }
+                    plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                }
+                plan::Entry::Symlink(current_src)
+                    if self.is_stowed(plan, root_src, &dest, &current_src) =>
+                {
+                    self.warn(
+                        Some(p),
+                        &dest,
+                        "overridden",
+                        format_args!(
+                            "override symlink {} from {} to {}",
+                            dest.display(),
+                            current_src.display(),
+                            link_target.display()
+                        ),
+                    )?;
+                    plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                }
+                // already available through a folded parent directory
+                plan::Entry::File if self.is_source(&dest, &src) => continue,
+                plan::Entry::File if self.is_hardlink(&dest, &src)? => {
+                    if self.options.hardlink {
+                        continue;
+                    }
+                    plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                }
+                plan::Entry::File if self.is_written(&dest)? => {
+                    plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                }
+                entry @ (plan::Entry::Symlink(_) | plan::Entry::File) => {
+                    if !self.resolve_conflict(plan, root_dest, p, &src, &dest, &entry)? {
+                        continue;
+                    }
+                }
+                plan::Entry::Directory => {
+                    let resolution = match self.options.conflict_policy {
+                        plan::ConflictPolicy::Skip => plan::ConflictPolicy::Skip,
+                        _ => plan::ConflictPolicy::Abort,
+                    };
+                    let reason = "existing directory".to_string();
+                    plan.conflict(p, &dest, reason, resolution);
+                    continue;
+                }
+            }
 
-        Command::new(&mut warnings_output, linker.as_mut())
-            .unstow(
-                &src,
-                &dest,
-                vec!["package-1".to_string(), "package-2".to_string()],
-            )
-            .expect("shouldn't fail");
+            let operation = if self.options.hardlink {
+                plan::Operation::CreateHardlink {
+                    source: src,
+                    destination: dest,
+                }
+            } else {
+                plan::Operation::CreateSymlink {
+                    source: link_target,
+                    destination: dest,
+                }
+            };
+            plan.push(p, operation);
+        }
 
-        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
-        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+        Ok(())
+    }
 
-        assert_eq!(
-            vec![
-                // handle package1 root
-                "readlink /home/jdoe/file-other",
-                "readlink /home/jdoe/subdirectory/file-2",
-                "readlink /home/jdoe/file-1",
-                "rm /home/jdoe/file-1",
-                "readlink /home/jdoe/file-2",
-                // handle package2 root
-                "readlink /home/jdoe/file-other",
-                "readlink /home/jdoe/subdirectory/file-2",
-                "readlink /home/jdoe/file-2",
-                "rm /home/jdoe/file-2",
-                // handle package2 subdirectory
-                "readlink /home/jdoe/file-other",
-                "readlink /home/jdoe/subdirectory/file-2",
-                "rm /home/jdoe/subdirectory/file-2"
-            ],
-            output.trim().split('\n').collect::<Vec<&str>>(),
-        );
-        assert_eq!("", warning.trim())
+    /// Location of a package file in the target directory.
+    fn destination(&self, root_dest: &path::Destination, file: &str) -> std::path::PathBuf {
+        let file = variant::base(file);
+        let file = template::rendered(&file);
+        if self.options.dotfiles {
+            root_dest.join(&path::dotfiles(file))
+        } else {
+            root_dest.join(file)
+        }
     }
 
-    fn find_subset(haystack: &[&str], needles: &[&str]) -> bool {
-        for i in 0..haystack.len() - needles.len() + 1 {
-            if haystack[i..i + needles.len()] == needles[..] {
-                return true;
+    /// Lists the files and directories of the package to use on the machine.
+    fn entries(&self, package: &package::Package) -> Result<Vec<String>, Error> {
+        let entries = package
+            .read_entries()?
+            .collect::<Result<Vec<String>, Error>>()?;
+
+        Ok(variant::select(entries, &self.options.machine))
+    }
+
+    fn package<'p>(
+        &self,
+        root_src: &path::Source,
+        name: &'p str,
+    ) -> Result<package::Package<'p>, Error> {
+        Ok(package::Package::new(root_src, name)?.with_ignore(&self.options.ignore))
+    }
+
+    /// Counts, for each directory, how many of the packages provide it.
+    fn count_contributors(
+        &self,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        packages: &[String],
+    ) -> Result<std::collections::HashMap<std::path::PathBuf, usize>, Error> {
+        let mut contributors = std::collections::HashMap::new();
+        for p in packages.iter() {
+            let package = self.package(root_src, p)?;
+            for dir in self.entries(&package)? {
+                if package.is_directory(&dir) {
+                    let dir = self.destination(root_dest, &dir);
+                    *contributors.entry(dir).or_insert(0) += 1;
+                }
             }
         }
 
-        false
+        Ok(contributors)
+    }
+
+    /// Plans what is required for a package directory to be available in the
+    /// target directory and returns whether its content needs to be stowed.
+    /// When the package is the only one to provide the directory, the whole
+    /// directory is linked instead.
+    fn plan_stow_directory(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        package: &str,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+        foldable: bool,
+    ) -> Result<bool, Error> {
+        match self.inspect(plan, destination)? {
+            plan::Entry::Directory => Ok(true),
+            plan::Entry::Missing if foldable => {
+                let parent_directory = destination
+                    .parent()
+                    .ok_or_else(|| Error::ParentDirectory(destination.display().to_string()))?;
+                let link_target = self.link_target(plan, parent_directory, source);
+                plan.push(
+                    package,
+                    plan::Operation::CreateSymlink {
+                        source: link_target,
+                        destination: destination.to_path_buf(),
+                    },
+                );
+                Ok(false)
+            }
+            plan::Entry::Missing => {
+                plan.push(
+                    package,
+                    plan::Operation::CreateDirectory(destination.to_path_buf()),
+                );
+                Ok(true)
+            }
+            plan::Entry::Symlink(target)
+                if self.resolve_symlink(plan, destination, &target) == source =>
+            {
+                if foldable {
+                    return Ok(false);
+                }
+                // the directory content has to be handled file by file now
+                plan.push(
+                    package,
+                    plan::Operation::DeleteFile(destination.to_path_buf()),
+                );
+                plan.push(
+                    package,
+                    plan::Operation::CreateDirectory(destination.to_path_buf()),
+                );
+                Ok(true)
+            }
+            plan::Entry::Symlink(target)
+                if self.is_stowed(plan, root_src, destination, &target) =>
+            {
+                self.plan_unfold(plan, root_src, destination, &target)?;
+                Ok(true)
+            }
+            plan::Entry::Symlink(_)
+                if matches!(self.linker.directory_exists(destination), Ok(true)) =>
+            {
+                Ok(true)
+            }
+            entry => {
+                let reason = match entry {
+                    plan::Entry::Symlink(target) => {
+                        format!("existing symlink to {}", target.display())
+                    }
+                    _ => "existing file".to_string(),
+                };
+                let resolution = match self.options.conflict_policy {
+                    plan::ConflictPolicy::Skip => plan::ConflictPolicy::Skip,
+                    _ => plan::ConflictPolicy::Abort,
+                };
+                plan.conflict(package, destination, reason, resolution);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Replaces a symlink to a directory of another package by a real
+    /// directory containing a symlink for each of its entries, so that
+    /// several packages can share it.
+    fn plan_unfold(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        destination: &std::path::Path,
+        target: &std::path::Path,
+    ) -> Result<(), Error> {
+        let root = self.linker.canonicalize(root_src.as_ref())?;
+        let folded = self.resolve_symlink(plan, destination, target);
+        let folded = folded.strip_prefix(&root).map_err(|e| {
+            Error::Generic(format!("cannot unfold {}: {}", destination.display(), e))
+        })?;
+
+        let mut components = folded.components();
+        let owner = components
+            .next()
+            .and_then(|c| c.as_os_str().to_str())
+            .ok_or_else(|| {
+                Error::Generic(format!(
+                    "cannot find the package owning {}",
+                    destination.display()
+                ))
+            })?;
+        let directory = components.as_path().to_str().ok_or_else(|| {
+            Error::Generic(format!(
+                "cannot convert path {} to string",
+                components.as_path().display()
+            ))
+        })?;
+
+        let package = self.package(root_src, owner)?;
+        plan.push(
+            owner,
+            plan::Operation::DeleteFile(destination.to_path_buf()),
+        );
+        plan.push(
+            owner,
+            plan::Operation::CreateDirectory(destination.to_path_buf()),
+        );
+
+        let children = package
+            .read_children(directory)?
+            .collect::<Result<Vec<String>, Error>>()?;
+        for child in variant::select(children, &self.options.machine) {
+            let name = std::path::Path::new(&child)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| Error::Generic(format!("cannot get {} basename", child)))?;
+            let name = variant::base(name);
+            let name = if self.options.dotfiles {
+                path::dotfiles(&name)
+            } else {
+                name
+            };
+            let link_target = self.link_target(plan, destination, &root.join(owner).join(&child));
+            plan.push(
+                owner,
+                plan::Operation::CreateSymlink {
+                    source: link_target,
+                    destination: destination.join(name),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Executes the plan through the linker, only if none of the conflicts
+    /// detected while computing it requires to abort. All the conflicts are
+    /// reported once done.
+    pub fn execute(&mut self, plan: &plan::Plan) -> Result<(), Error> {
+        // dropped first, since the plan may create new links in their place
+        if plan.is_valid() {
+            for destination in plan.forgotten.iter() {
+                self.state.forget(destination);
+            }
+        }
+
+        let state = &mut self.state;
+        let mut executed = 0;
+        let result = plan.execute_with(self.linker, |action| {
+            state.record(action);
+            executed += 1;
+        });
+
+        if self.options.format == event::Format::Json {
+            let (outcome, reported) = if !plan.is_valid() {
+                ("aborted", plan.actions.len())
+            } else if self.options.dry_run {
+                ("planned", executed)
+            } else {
+                ("done", executed)
+            };
+            for action in plan.actions[..reported].iter() {
+                self.emit(event::Event::action(action, outcome))?;
+            }
+            if let (Err(err), Some(action)) = (&result, plan.actions.get(executed)) {
+                if plan.is_valid() {
+                    self.emit(
+                        event::Event::action(action, "failed").with_message(&err.to_string()),
+                    )?;
+                }
+            }
+        }
+
+        for conflict in plan.conflicts.iter() {
+            match self.options.format {
+                event::Format::Text => writeln!(self.logger, "conflict: {}", conflict)
+                    .map_err(|e| Error::Generic(format!("failed to print conflict: {}", e)))?,
+                event::Format::Json => self.emit(event::Event::conflict(conflict))?,
+            }
+        }
+
+        result
+    }
+
+    /// Records a conflict on a file standing where a link should be created
+    /// and returns whether the link should still be created.
+    fn resolve_conflict(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_dest: &path::Destination,
+        package: &str,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+        entry: &plan::Entry,
+    ) -> Result<bool, Error> {
+        let policy = self.options.conflict_policy;
+        let reason = match entry {
+            plan::Entry::Symlink(target) => format!("existing symlink to {}", target.display()),
+            _ if self
+                .state
+                .link(destination)
+                .is_some_and(|l| l.checksum.is_some()) =>
+            {
+                "existing file modified since it was written".to_string()
+            }
+            _ => "existing file".to_string(),
+        };
+
+        match policy {
+            plan::ConflictPolicy::Abort | plan::ConflictPolicy::Skip => {
+                plan.conflict(package, destination, reason, policy);
+                Ok(false)
+            }
+            plan::ConflictPolicy::Overwrite => {
+                plan.conflict(package, destination, reason, policy);
+                plan.push(
+                    package,
+                    plan::Operation::DeleteFile(destination.to_path_buf()),
+                );
+                Ok(true)
+            }
+            plan::ConflictPolicy::Backup => {
+                let mut backup = backup_path(root_dest, package, destination)?;
+                let mut count = 0;
+                while self.inspect(plan, &backup)? != plan::Entry::Missing {
+                    // an older backup of the same file is kept as is
+                    count += 1;
+                    let mut name = backup_path(root_dest, package, destination)?.into_os_string();
+                    name.push(format!(".{}", count));
+                    backup = name.into();
+                }
+
+                let backup_directory = backup
+                    .parent()
+                    .ok_or_else(|| Error::ParentDirectory(backup.display().to_string()))?;
+                if !self.plan_directory(plan, package, backup_directory)? {
+                    return Ok(false);
+                }
+
+                let reason = format!("{}, moved to {}", reason, backup.display());
+                plan.conflict(package, destination, reason, policy);
+                plan.push(
+                    package,
+                    plan::Operation::Backup {
+                        source: destination.to_path_buf(),
+                        destination: backup,
+                    },
+                );
+                Ok(true)
+            }
+            plan::ConflictPolicy::Adopt => {
+                if let plan::Entry::Symlink(_) = entry {
+                    let reason = format!("{}, symlinks cannot be adopted", reason);
+                    plan.conflict(package, destination, reason, plan::ConflictPolicy::Abort);
+                    return Ok(false);
+                }
+
+                let reason = format!("{}, moved to {}", reason, source.display());
+                plan.conflict(package, destination, reason, policy);
+                plan.push(
+                    package,
+                    plan::Operation::Rename {
+                        source: destination.to_path_buf(),
+                        destination: source.to_path_buf(),
+                    },
+                );
+                Ok(true)
+            }
+        }
+    }
+
+    /// Plans the writing of the package file `file`, located at `source`, in
+    /// the target directory: templates are rendered and other files copied.
+    /// A file previously written there is replaced, unless it has been
+    /// modified since, which is handled as a conflict.
+    fn plan_write(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        package: &package::Package,
+        file: &str,
+        source: &std::path::Path,
+    ) -> Result<(), Error> {
+        let content = package.read_file(file)?;
+        let destination = self.destination(root_dest, file);
+        let (checksum, operation) = if template::is_template(&variant::base(file)) {
+            let template = String::from_utf8(content).map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: source.display().to_string(),
+                    reason: e.to_string(),
+                })
+            })?;
+            let content = self
+                .options
+                .variables
+                .render(&source.display().to_string(), &template)?;
+            (
+                state::checksum(content.as_bytes()),
+                plan::Operation::WriteFile {
+                    source: source.to_path_buf(),
+                    destination: destination.clone(),
+                    content,
+                },
+            )
+        } else {
+            let checksum = state::checksum(&content);
+            (
+                checksum.clone(),
+                plan::Operation::CopyFile {
+                    source: source.to_path_buf(),
+                    destination: destination.clone(),
+                    checksum,
+                },
+            )
+        };
+        let destination = &destination;
+        let package = package.name();
+
+        match self.inspect(plan, destination)? {
+            plan::Entry::Missing => {}
+            plan::Entry::File if self.is_source(destination, source) => {
+                let resolution = match self.options.conflict_policy {
+                    plan::ConflictPolicy::Skip => plan::ConflictPolicy::Skip,
+                    _ => plan::ConflictPolicy::Abort,
+                };
+                let reason = "package file reached through a linked directory".to_string();
+                plan.conflict(package, destination, reason, resolution);
+                return Ok(());
+            }
+            plan::Entry::File if self.is_hardlink(destination, source)? => {
+                plan.push(
+                    package,
+                    plan::Operation::DeleteFile(destination.to_path_buf()),
+                );
+            }
+            plan::Entry::File
+                if state::checksum(&self.linker.read_file(destination)?) == checksum =>
+            {
+                return Ok(());
+            }
+            plan::Entry::File if self.is_written(destination)? => {
+                plan.push(
+                    package,
+                    plan::Operation::DeleteFile(destination.to_path_buf()),
+                );
+            }
+            plan::Entry::Symlink(target)
+                if self.is_stowed(plan, root_src, destination, &target) =>
+            {
+                self.warn(
+                    Some(package),
+                    destination,
+                    "overridden",
+                    format_args!(
+                        "override symlink {} from {} to a file written from {}",
+                        destination.display(),
+                        target.display(),
+                        source.display()
+                    ),
+                )?;
+                plan.push(
+                    package,
+                    plan::Operation::DeleteFile(destination.to_path_buf()),
+                );
+            }
+            plan::Entry::Directory => {
+                let resolution = match self.options.conflict_policy {
+                    plan::ConflictPolicy::Skip => plan::ConflictPolicy::Skip,
+                    _ => plan::ConflictPolicy::Abort,
+                };
+                plan.conflict(
+                    package,
+                    destination,
+                    "existing directory".to_string(),
+                    resolution,
+                );
+                return Ok(());
+            }
+            plan::Entry::File if self.options.conflict_policy == plan::ConflictPolicy::Adopt => {
+                let reason =
+                    "existing file, copied or rendered files cannot be adopted".to_string();
+                plan.conflict(package, destination, reason, plan::ConflictPolicy::Abort);
+                return Ok(());
+            }
+            entry => {
+                if !self.resolve_conflict(plan, root_dest, package, source, destination, &entry)? {
+                    return Ok(());
+                }
+            }
+        }
+
+        plan.push(package, operation);
+
+        Ok(())
+    }
+
+    /// Tells whether a package file is copied instead of linked.
+    fn is_copied(&self, package: &package::Package, file: &str) -> bool {
+        self.options.copy || package.is_copied(&variant::base(file))
+    }
+
+    /// Tells whether the regular file `destination` has been copied or
+    /// rendered by stow, and not modified since.
+    fn is_written(&mut self, destination: &std::path::Path) -> Result<bool, Error> {
+        let recorded = match self.state.link(destination) {
+            Some(state::Link {
+                checksum: Some(checksum),
+                ..
+            }) => checksum.clone(),
+            _ => return Ok(false),
+        };
+
+        let content = self.linker.read_file(destination)?;
+        Ok(state::checksum(&content) == recorded)
+    }
+
+    /// Tells whether `destination` is the package file `source` itself,
+    /// reached through a symlink to a package directory, e.g. below a folded
+    /// directory when stowing a sub-path of the package.
+    fn is_source(&mut self, destination: &std::path::Path, source: &std::path::Path) -> bool {
+        match (
+            self.linker.canonicalize(destination),
+            self.linker.canonicalize(source),
+        ) {
+            (Ok(destination), Ok(source)) => destination == source,
+            _ => false,
+        }
+    }
+
+    /// Tells whether the regular file `destination` is a hardlink to the
+    /// package file `source`, rather than `source` itself.
+    fn is_hardlink(
+        &mut self,
+        destination: &std::path::Path,
+        source: &std::path::Path,
+    ) -> Result<bool, Error> {
+        Ok(
+            !self.is_source(destination, source)
+                && self.linker.is_same_file(destination, source)?,
+        )
+    }
+
+    /// Checks whether a symlink located at `link` targets the stow directory.
+    fn is_stowed(
+        &mut self,
+        plan: &plan::Plan,
+        root_src: &path::Source,
+        link: &std::path::Path,
+        target: &std::path::Path,
+    ) -> bool {
+        let target = self.resolve_symlink(plan, link, target);
+        match self.linker.canonicalize(root_src.as_ref()) {
+            Ok(root_src) => target.starts_with(root_src),
+            Err(_) => target.starts_with(root_src),
+        }
+    }
+
+    /// Computes the canonical path a symlink points to, without requiring its
+    /// target to exist: the target is resolved from the directory containing
+    /// the link and only its closest existing ancestor is canonicalized.
+    fn resolve_symlink(
+        &mut self,
+        plan: &plan::Plan,
+        link: &std::path::Path,
+        target: &std::path::Path,
+    ) -> std::path::PathBuf {
+        let directory = match link.parent() {
+            Some(parent) => self.canonical_directory(plan, parent),
+            None => std::path::PathBuf::new(),
+        };
+
+        self.canonical_directory(plan, &path::normalize(&directory.join(target)))
+    }
+
+    /// Computes what a symlink created in `directory` should contain to
+    /// target `source`.
+    fn link_target(
+        &mut self,
+        plan: &plan::Plan,
+        directory: &std::path::Path,
+        source: &std::path::Path,
+    ) -> std::path::PathBuf {
+        if self.options.absolute {
+            return source.to_path_buf();
+        }
+
+        let directory = self.canonical_directory(plan, directory);
+        path::relative(&directory, source).unwrap_or_else(|| source.to_path_buf())
+    }
+
+    /// Canonicalizes a directory which may not exist yet, by canonicalizing
+    /// its closest existing ancestor. A symlink the plan replaces by a real
+    /// directory is not followed.
+    fn canonical_directory(
+        &mut self,
+        plan: &plan::Plan,
+        directory: &std::path::Path,
+    ) -> std::path::PathBuf {
+        let unfolded = plan.entry(directory) == Some(plan::Entry::Directory)
+            && self.linker.is_symlink(directory);
+        if !unfolded {
+            if let Ok(canonical) = self.linker.canonicalize(directory) {
+                return canonical;
+            }
+        }
+
+        match (directory.parent(), directory.file_name()) {
+            (Some(parent), Some(name)) => self.canonical_directory(plan, parent).join(name),
+            _ => directory.to_path_buf(),
+        }
+    }
+
+    fn plan_directory(
+        &mut self,
+        plan: &mut plan::Plan,
+        package: &str,
+        directory: &std::path::Path,
+    ) -> Result<bool, Error> {
+        let exists = match plan.entry(directory) {
+            Some(plan::Entry::Directory) => Ok(true),
+            Some(plan::Entry::Missing) => Ok(false),
+            Some(_) => Err("a file is in the way of the parent directory".to_string()),
+            None => self
+                .linker
+                .directory_exists(directory)
+                .map_err(|e| e.to_string()),
+        };
+
+        match exists {
+            Ok(true) => Ok(true),
+            Ok(false) => {
+                plan.push(
+                    package,
+                    plan::Operation::CreateDirectory(directory.to_path_buf()),
+                );
+                Ok(true)
+            }
+            Err(reason) => {
+                let resolution = match self.options.conflict_policy {
+                    plan::ConflictPolicy::Skip => plan::ConflictPolicy::Skip,
+                    _ => plan::ConflictPolicy::Abort,
+                };
+                plan.conflict(package, directory, reason, resolution);
+                Ok(false)
+            }
+        }
+    }
+
+    fn inspect(&mut self, plan: &plan::Plan, file: &std::path::Path) -> Result<plan::Entry, Error> {
+        if let Some(entry) = plan.entry(file) {
+            return Ok(entry);
+        }
+
+        if self.linker.is_symlink(file) {
+            return self.linker.read_link(file).map(plan::Entry::Symlink);
+        }
+
+        if let Ok(true) = self.linker.directory_exists(file) {
+            return Ok(plan::Entry::Directory);
+        }
+
+        if self.linker.file_exists(file)? {
+            return Ok(plan::Entry::File);
+        }
+
+        Ok(plan::Entry::Missing)
+    }
+
+    /// Reports something unusual about `destination`, which is `outcome`,
+    /// e.g. skipped.
+    fn warn(
+        &mut self,
+        package: Option<&str>,
+        destination: &std::path::Path,
+        outcome: &str,
+        message: std::fmt::Arguments,
+    ) -> Result<(), Error> {
+        match self.options.format {
+            event::Format::Text => writeln!(self.logger, "warning: {}", message)
+                .map_err(|e| Error::Generic(format!("failed to print warning: {}", e))),
+            event::Format::Json => {
+                let event = event::Event::new(event::Kind::Warning);
+                let event = match package {
+                    Some(package) => event.with_package(package),
+                    None => event,
+                };
+                self.emit(
+                    event
+                        .with_destination(destination)
+                        .with_outcome(outcome)
+                        .with_message(&message.to_string()),
+                )
+            }
+        }
+    }
+
+    fn emit(&mut self, event: event::Event) -> Result<(), Error> {
+        writeln!(self.logger, "{}", event)
+            .map_err(|e| Error::Generic(format!("failed to print event: {}", e)))
+    }
+
+    /// Plans the removal of the links pointing to the package. When
+    /// `stale_only` is set, only the links to files which are not part of the
+    /// package anymore are removed.
+    fn plan_unstow_package(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        p: &str,
+        stale_only: bool,
+    ) -> Result<(), Error> {
+        match self.package(root_src, p) {
+            Ok(package) => {
+                self.plan_unstow_package_files(plan, root_src, root_dest, &package, stale_only)?
+            }
+            Err(Error::PackageNotFound(_))
+                if !self
+                    .state
+                    .links(package::split(p).0, root_dest.as_ref())
+                    .is_empty() => {}
+            Err(err) => return Err(err),
+        }
+
+        self.plan_unstow_recorded(plan, root_src, root_dest, p, stale_only)
+    }
+
+    /// Removes the links recorded in the state for a package, as long as they
+    /// still target the file they were created for. It allows to unstow a
+    /// package which has been deleted or renamed.
+    fn plan_unstow_recorded(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        p: &str,
+        stale_only: bool,
+    ) -> Result<(), Error> {
+        let (p, subpath) = package::split(p);
+        let package_src = self.linker.canonicalize(root_src.as_ref())?.join(p);
+        for link in self.state.links(p, root_dest.as_ref()) {
+            if plan.entry(&link.destination).is_some() {
+                continue;
+            }
+
+            let target = self.resolve_symlink(plan, &link.destination, &link.source);
+            if !subpath.is_empty() && !target.starts_with(package_src.join(subpath)) {
+                continue;
+            }
+
+            if link.checksum.is_some() {
+                self.plan_unstow_written(plan, p, &link, stale_only)?;
+                continue;
+            }
+
+            if link.hardlink {
+                self.plan_unstow_hardlink(plan, p, &link, stale_only)?;
+                continue;
+            }
+
+            if !self.linker.is_symlink(&link.destination) {
+                plan.forget(&link.destination);
+                continue;
+            }
+
+            let source = self.linker.read_link(&link.destination)?;
+            if source != link.source {
+                self.warn(
+                    Some(p),
+                    &link.destination,
+                    "skipped",
+                    format_args!(
+                        "skip {}: recorded as a link to {} but links to {}",
+                        link.destination.display(),
+                        link.source.display(),
+                        source.display()
+                    ),
+                )?;
+                continue;
+            }
+
+            if stale_only && self.exists(&target) {
+                continue;
+            }
+
+            plan.push(p, plan::Operation::DeleteFile(link.destination.clone()));
+            if self.options.restore_backups {
+                self.plan_restore_backup(plan, p, &link.destination)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes a hardlink recorded in the state, as long as it is still the
+    /// same file as the package file it was created from. With `stale_only`,
+    /// the hardlink is kept, since its package file still exists.
+    fn plan_unstow_hardlink(
+        &mut self,
+        plan: &mut plan::Plan,
+        p: &str,
+        link: &state::Link,
+        stale_only: bool,
+    ) -> Result<(), Error> {
+        if self.linker.is_symlink(&link.destination)
+            || !matches!(self.linker.file_exists(&link.destination), Ok(true))
+        {
+            plan.forget(&link.destination);
+            return Ok(());
+        }
+
+        if !self.is_hardlink(&link.destination, &link.source)? {
+            return self.warn(
+                Some(p),
+                &link.destination,
+                "skipped",
+                format_args!(
+                    "skip {}: not a hardlink to {} anymore",
+                    link.destination.display(),
+                    link.source.display()
+                ),
+            );
+        }
+
+        if stale_only {
+            return Ok(());
+        }
+
+        plan.push(p, plan::Operation::DeleteFile(link.destination.clone()));
+        if self.options.restore_backups {
+            self.plan_restore_backup(plan, p, &link.destination)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a file copied or rendered from a package file, recorded in the
+    /// state, as long as it hasn't been modified since. With `stale_only`, the
+    /// file is removed only when its package file doesn't exist anymore.
+    fn plan_unstow_written(
+        &mut self,
+        plan: &mut plan::Plan,
+        p: &str,
+        link: &state::Link,
+        stale_only: bool,
+    ) -> Result<(), Error> {
+        if self.linker.is_symlink(&link.destination)
+            || !matches!(self.linker.file_exists(&link.destination), Ok(true))
+        {
+            plan.forget(&link.destination);
+            return Ok(());
+        }
+
+        if stale_only && self.exists(&link.source) {
+            return Ok(());
+        }
+
+        if !self.is_written(&link.destination)? {
+            return self.warn(
+                Some(p),
+                &link.destination,
+                "skipped",
+                format_args!(
+                    "skip {}: modified since it was written",
+                    link.destination.display()
+                ),
+            );
+        }
+
+        plan.push(p, plan::Operation::DeleteFile(link.destination.clone()));
+        if self.options.restore_backups {
+            self.plan_restore_backup(plan, p, &link.destination)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the links of the package files, a link being removed only when
+    /// it targets the exact package file it stands for. With `stale_only`,
+    /// removes instead the dangling links to files the package doesn't
+    /// contain anymore.
+    fn plan_unstow_package_files(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        package: &package::Package,
+        stale_only: bool,
+    ) -> Result<(), Error> {
+        if stale_only {
+            return self.plan_unstow_stale_files(plan, root_src, root_dest, package);
+        }
+
+        let p = package.name();
+        let package_src = self.linker.canonicalize(root_src.as_ref())?.join(p);
+        let mut skipped_directories: Vec<std::path::PathBuf> = Vec::new();
+
+        for file in self.entries(package)? {
+            if skipped_directories
+                .iter()
+                .any(|directory| std::path::Path::new(&file).starts_with(directory))
+            {
+                continue;
+            }
+
+            let src = package_src.join(&file);
+            let dest = self.destination(root_dest, &file);
+            let is_directory = package.is_directory(&file);
+
+            match self.inspect(plan, &dest)? {
+                plan::Entry::Symlink(target) => {
+                    let target = self.resolve_symlink(plan, &dest, &target);
+                    // another variant of the file may have been selected
+                    // when the package was stowed
+                    let is_variant = target
+                        .strip_prefix(&package_src)
+                        .ok()
+                        .and_then(|rel| rel.to_str())
+                        .map(|rel| variant::base(rel) == variant::base(&file))
+                        .unwrap_or(false);
+                    if is_variant {
+                        plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                        if self.options.restore_backups {
+                            self.plan_restore_backup(plan, p, &dest)?;
+                        }
+                    } else if !is_directory
+                        || !matches!(self.linker.directory_exists(&dest), Ok(true))
+                    {
+                        self.warn(
+                            Some(p),
+                            &dest,
+                            "skipped",
+                            format_args!(
+                                "skip {}: links to {} instead of {}",
+                                dest.display(),
+                                target.display(),
+                                src.display()
+                            ),
+                        )?;
+                    }
+
+                    if is_directory && is_variant {
+                        skipped_directories.push(file.into());
+                    }
+                }
+                plan::Entry::Directory if is_directory => {}
+                plan::Entry::Missing => {
+                    if is_directory {
+                        skipped_directories.push(file.into());
+                    }
+                }
+                plan::Entry::File if self.is_source(&dest, &src) => {
+                    self.warn(
+                        Some(p),
+                        &dest,
+                        "skipped",
+                        format_args!("skip {}: linked through a parent directory", dest.display()),
+                    )?;
+                }
+                plan::Entry::File if self.is_hardlink(&dest, &src)? => {
+                    plan.push(p, plan::Operation::DeleteFile(dest.clone()));
+                    if self.options.restore_backups {
+                        self.plan_restore_backup(plan, p, &dest)?;
+                    }
+                }
+                plan::Entry::File
+                    if self
+                        .state
+                        .link(&dest)
+                        .is_some_and(|l| l.checksum.is_some() || l.hardlink) =>
+                {
+                    // written files and replaced hardlinks are handled with
+                    // the recorded links
+                }
+                plan::Entry::Directory | plan::Entry::File => {
+                    self.warn(
+                        Some(p),
+                        &dest,
+                        "skipped",
+                        format_args!("skip {}: not a symlink", dest.display()),
+                    )?;
+                    if is_directory {
+                        skipped_directories.push(file.into());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the dangling links, in the directories of the package, which
+    /// target a file of the package directory the package doesn't contain
+    /// anymore.
+    fn plan_unstow_stale_files(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+        package: &package::Package,
+    ) -> Result<(), Error> {
+        let p = package.name();
+        let files = package
+            .read_entries()?
+            .collect::<Result<std::collections::HashSet<String>, Error>>()?;
+        let package_src = self.linker.canonicalize(root_src.as_ref())?.join(p);
+
+        for dir in package.read_dirs()? {
+            let dir = dir?;
+            let dir_src_path = package_src.join(&dir);
+            let dir_dest_path = self.destination(root_dest, &dir);
+            if !self.linker.directory_exists(&dir_dest_path)? {
+                continue;
+            }
+
+            // a folded directory is handled as a whole from its parent
+            if let plan::Entry::Symlink(target) = self.inspect(plan, &dir_dest_path)? {
+                if self.is_stowed(plan, root_src, &dir_dest_path, &target) {
+                    continue;
+                }
+            }
+
+            for destination_file in self.linker.list_symlinks(&dir_dest_path)? {
+                if plan.entry(&destination_file).is_some() {
+                    continue;
+                }
+
+                let target_link = self.linker.read_link(&destination_file)?;
+                let target_link = self.resolve_symlink(plan, &destination_file, &target_link);
+                if target_link.parent() != Some(dir_src_path.as_path()) {
+                    continue;
+                }
+
+                let file = target_link
+                    .file_name()
+                    .map(|name| std::path::Path::new(&dir).join(name));
+                let is_package_file = file
+                    .and_then(|f| f.to_str().map(|f| files.contains(f)))
+                    .unwrap_or(false);
+                if is_package_file || self.exists(&target_link) {
+                    continue;
+                }
+
+                plan.push(p, plan::Operation::DeleteFile(destination_file.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the directories left empty by the links the plan deletes and
+    /// the backups it restores, from the deepest one up to the target
+    /// directory, which is kept. A directory reached through a symlinked
+    /// parent is kept as well when it actually lies outside of the target
+    /// directory or in the stow directory.
+    fn plan_remove_empty_directories(
+        &mut self,
+        plan: &mut plan::Plan,
+        root_src: &path::Source,
+        root_dest: &path::Destination,
+    ) -> Result<(), Error> {
+        let target = self.linker.canonicalize(root_dest.as_ref())?;
+        let stow_directory = self.linker.canonicalize(root_src.as_ref())?;
+        let mut candidates = std::collections::BTreeMap::new();
+        for action in plan.actions.iter() {
+            let file = match &action.operation {
+                plan::Operation::DeleteFile(file) => file,
+                plan::Operation::Rename { source, .. } => source,
+                _ => continue,
+            };
+            if let Some(parent) = file.parent() {
+                let depth = parent.components().count();
+                candidates.insert((depth, parent.to_path_buf()), action.package.clone());
+            }
+        }
+
+        while let Some(((_, directory), package)) = candidates.pop_last() {
+            if directory == root_dest.as_ref() || !directory.starts_with(root_dest) {
+                continue;
+            }
+
+            if plan.entry(&directory).is_some()
+                || self.linker.is_symlink(&directory)
+                || !matches!(self.linker.directory_exists(&directory), Ok(true))
+            {
+                continue;
+            }
+
+            let canonical = self.linker.canonicalize(&directory)?;
+            if canonical == target
+                || !canonical.starts_with(&target)
+                || canonical.starts_with(&stow_directory)
+            {
+                continue;
+            }
+
+            let mut entries = self.linker.read_directory(&directory)?.into_iter();
+            if !entries.all(|entry| plan.entry(&entry) == Some(plan::Entry::Missing)) {
+                continue;
+            }
+
+            plan.push(
+                &package,
+                plan::Operation::RemoveDirectory(directory.clone()),
+            );
+            if let Some(parent) = directory.parent() {
+                let depth = parent.components().count();
+                candidates.insert((depth, parent.to_path_buf()), package);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves back the file backed up when the link at `destination` was
+    /// created, as recorded in the state.
+    fn plan_restore_backup(
+        &mut self,
+        plan: &mut plan::Plan,
+        package: &str,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        let backup = match self.state.link(destination).and_then(|l| l.backup.clone()) {
+            Some(backup) => backup,
+            None => return Ok(()),
+        };
+
+        if let plan::Entry::Missing | plan::Entry::Directory = self.inspect(plan, &backup)? {
+            return self.warn(
+                Some(package),
+                destination,
+                "skipped",
+                format_args!(
+                    "skip restoring {}: backup {} not found",
+                    destination.display(),
+                    backup.display()
+                ),
+            );
+        }
+
+        if self.options.format == event::Format::Text {
+            writeln!(
+                self.logger,
+                "restore: {} from {}",
+                destination.display(),
+                backup.display()
+            )
+            .map_err(|e| Error::Generic(format!("failed to print restore: {}", e)))?;
+        }
+        plan.push(
+            package,
+            plan::Operation::Rename {
+                source: backup,
+                destination: destination.to_path_buf(),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+/// Tells whether a file below `directory` is a variant, a template or is
+/// renamed in dotfiles mode, in which case the directory cannot be linked as
+/// a whole.
+fn has_renamed_files(entries: &[String], directory: &str, dotfiles: bool) -> bool {
+    entries.iter().any(|entry| {
+        std::path::Path::new(entry)
+            .strip_prefix(directory)
+            .map(|rest| {
+                rest.to_str()
+                    .map(|rest| {
+                        !rest.is_empty()
+                            && (rest.contains(variant::SEPARATOR)
+                                || template::is_template(rest)
+                                || (dotfiles && path::dotfiles(rest) != rest))
+                    })
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// Location where a file in conflict with a package is moved to, so it can
+/// be found back when the package gets unstowed.
+fn backup_path(
+    root_dest: &path::Destination,
+    package: &str,
+    destination: &std::path::Path,
+) -> Result<std::path::PathBuf, Error> {
+    let file = destination.strip_prefix(root_dest).map_err(|e| {
+        Error::Generic(format!(
+            "cannot compute backup location of {}: {}",
+            destination.display(),
+            e
+        ))
+    })?;
+
+    Ok(root_dest.join(BACKUP_DIRECTORY).join(package).join(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linker;
+
+    #[test]
+    fn stow_packages() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .stow(
+                &src,
+                &dest,
+                vec!["package-1".to_string(), "package-2".to_string()],
+            )
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "mkdir -p /home/jdoe",
+                "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
+                "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+                "rm /home/jdoe/file-1",
+                "ln -s golden-files/package-2/file-1 /home/jdoe/file-1",
+                "ln -s golden-files/package-2/subdirectory /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+
+        assert_eq!(vec![
+            "warning: override symlink /home/jdoe/file-1 from golden-files/package-1/file-1 to golden-files/package-2/file-1",
+        ], warning.trim().split('\n').collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn stow_package_subpaths() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut command = Command::new(&mut warnings_output, linker.as_mut());
+        command
+            .stow(
+                &src,
+                &dest,
+                vec![
+                    "package-1/file-2".to_string(),
+                    "package-5/dot-config/nvim".to_string(),
+                ],
+            )
+            .expect("shouldn't fail");
+        command
+            .unstow(&src, &dest, vec!["package-1/file-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                // stow
+                "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+                "mkdir -p /home/jdoe/dot-config",
+                "ln -s ../golden-files/package-5/dot-config/nvim /home/jdoe/dot-config/nvim",
+                // unstow
+                "readlink /home/jdoe/file-2",
+                "rm /home/jdoe/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn stow_packages_absolute() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![(
+                "/home/jdoe/file-1".into(),
+                "golden-files/package-2/file-1".into(),
+            )],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                absolute: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/file-1",
+                "ln -s /home/jdoe/golden-files/package-2/subdirectory /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_symlinked_target() {
+        let ctx = linker::tests::TestWithTempDir::new("symlinked-target");
+        std::fs::create_dir_all(ctx.dir.join("dots/zsh")).unwrap();
+        std::fs::write(ctx.dir.join("dots/zsh/.zshrc"), "").unwrap();
+        std::fs::create_dir_all(ctx.dir.join("home")).unwrap();
+        std::fs::create_dir_all(ctx.dir.join("a/b")).unwrap();
+        std::os::unix::fs::symlink("../../home", ctx.dir.join("a/b/h")).unwrap();
+
+        let root_src = ctx.dir.join("dots");
+        let root_dest = ctx.dir.join("a/b/h");
+        let src: path::Source = root_src.as_path().into();
+        let dest: path::Destination = root_dest.as_path().into();
+        let mut output = Vec::new();
+        let mut linker = linker::Filesystem;
+
+        for _ in 0..2 {
+            Command::new(&mut output, &mut linker)
+                .stow(&src, &dest, vec!["zsh".to_string()])
+                .expect("shouldn't fail");
+            assert_eq!(
+                std::path::PathBuf::from("../dots/zsh/.zshrc"),
+                std::fs::read_link(ctx.dir.join("home/.zshrc")).unwrap()
+            );
+        }
+
+        Command::new(&mut output, &mut linker)
+            .restow(&src, &dest, vec!["zsh".to_string()])
+            .expect("shouldn't fail");
+        Command::new(&mut output, &mut linker)
+            .unstow(&src, &dest, vec!["zsh".to_string()])
+            .expect("shouldn't fail");
+
+        assert!(std::fs::symlink_metadata(ctx.dir.join("home/.zshrc")).is_err());
+        assert!(ctx.dir.join("dots/zsh/.zshrc").exists());
+        assert_eq!("", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn stow_packages_no_folding() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                no_folding: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-3".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "mkdir -p /home/jdoe/subdirectory",
+                "ln -s ../golden-files/package-3/subdirectory/file-3 /home/jdoe/subdirectory/file-3",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_ignored_files() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                no_folding: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-4".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "ln -s golden-files/package-4/file-4 /home/jdoe/file-4",
+                "mkdir -p /home/jdoe/subdirectory",
+                "ln -s ../golden-files/package-4/subdirectory/file-4 /home/jdoe/subdirectory/file-4",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_dotfiles() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                dotfiles: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-5".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "mkdir -p /home/jdoe/.config",
+                "mkdir -p /home/jdoe/.config/nvim",
+                "ln -s ../../golden-files/package-5/dot-config/nvim/dot-luarc.json /home/jdoe/.config/nvim/.luarc.json",
+                "ln -s ../../golden-files/package-5/dot-config/nvim/init.lua /home/jdoe/.config/nvim/init.lua",
+                "ln -s golden-files/package-5/dot-zshrc /home/jdoe/.zshrc",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_variants() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                machine: variant::Machine {
+                    os: "linux".to_string(),
+                    host: "personal-laptop".to_string(),
+                    user: "jdoe".to_string(),
+                    tags: vec!["work".to_string()],
+                },
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-6".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "ln -s golden-files/package-6/gitconfig /home/jdoe/gitconfig",
+                "mkdir -p /home/jdoe/nvim",
+                "ln -s ../golden-files/package-6/nvim/init.lua##tag.work /home/jdoe/nvim/init.lua",
+                "ln -s golden-files/package-6/zshrc##os.linux /home/jdoe/zshrc",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn stow_packages_written_files() {
+        let rendered = "[user]\n\tname = jdoe\n";
+        let testcases = vec![
+            (
+                vec![],
+                None,
+                vec![
+                    "mkdir -p /home/jdoe/git",
+                    "write /home/jdoe/git/config",
+                    "mkdir -p /home/jdoe/git/hooks",
+                    "cp /home/jdoe/golden-files/package-7/git/hooks/pre-commit /home/jdoe/git/hooks/pre-commit",
+                    "ln -s ../golden-files/package-7/git/ignore /home/jdoe/git/ignore",
+                ],
+                "",
+            ),
+            (
+                vec![("/home/jdoe/git/config", rendered)],
+                Some(rendered),
+                vec![
+                    "mkdir -p /home/jdoe/git/hooks",
+                    "cp /home/jdoe/golden-files/package-7/git/hooks/pre-commit /home/jdoe/git/hooks/pre-commit",
+                    "ln -s ../golden-files/package-7/git/ignore /home/jdoe/git/ignore",
+                ],
+                "",
+            ),
+            (
+                vec![("/home/jdoe/git/config", "[user]\n\tname = john\n")],
+                Some("[user]\n\tname = john\n"),
+                vec![
+                    "rm /home/jdoe/git/config",
+                    "write /home/jdoe/git/config",
+                    "mkdir -p /home/jdoe/git/hooks",
+                    "cp /home/jdoe/golden-files/package-7/git/hooks/pre-commit /home/jdoe/git/hooks/pre-commit",
+                    "ln -s ../golden-files/package-7/git/ignore /home/jdoe/git/ignore",
+                ],
+                "",
+            ),
+            (
+                vec![("/home/jdoe/git/config", "[user]\n\tname = jdoe\n[core]\n")],
+                Some(rendered),
+                vec![""],
+                "conflict: /home/jdoe/git/config (package-7): existing file modified since it was written, aborted",
+            ),
+        ];
+
+        for (contents, recorded, expected_output, expected_warning) in testcases {
+            let mut commands_output = std::io::BufWriter::new(Vec::new());
+            let mut warnings_output = std::io::BufWriter::new(Vec::new());
+            let mut directories = vec!["/home/jdoe".into()];
+            if !contents.is_empty() {
+                directories.push("/home/jdoe/git".into());
+            }
+            let noop = linker::Noop {
+                current_directory: "/home/jdoe".into(),
+                directories,
+                regular_files: contents.iter().map(|(f, _)| f.into()).collect(),
+                contents: contents
+                    .iter()
+                    .map(|(f, c)| (f.into(), c.as_bytes().to_vec()))
+                    .collect(),
+                ..Default::default()
+            };
+            let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+            let mut state = state::State::default();
+            if let Some(content) = recorded {
+                state.record(&plan::Action {
+                    package: "package-7".to_string(),
+                    operation: plan::Operation::WriteFile {
+                        source: "/home/jdoe/golden-files/package-7/git/config.tmpl".into(),
+                        destination: "/home/jdoe/git/config".into(),
+                        content: content.to_string(),
+                    },
+                });
+            }
+
+            let src: path::Source = "golden-files".into();
+            let dest: path::Destination = "/home/jdoe".into();
+
+            let mut cmd = Command::new(&mut warnings_output, linker.as_mut())
+                .with_options(Options {
+                    variables: template::Variables::default().with("user", "jdoe"),
+                    ..Default::default()
+                })
+                .with_state(state);
+            let result = cmd.stow(&src, &dest, vec!["package-7".to_string()]);
+            if expected_warning.is_empty() {
+                result.expect("shouldn't fail");
+                assert_eq!(
+                    Some(state::checksum(rendered.as_bytes())),
+                    cmd.state()
+                        .link("/home/jdoe/git/config".as_ref())
+                        .and_then(|l| l.checksum.clone())
+                );
+            } else {
+                assert_eq!(Err(Error::Conflicts(1)), result);
+            }
+            drop(cmd);
+
+            let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+            let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+            assert_eq!(
+                expected_output,
+                output.trim().split('\n').collect::<Vec<&str>>()
+            );
+            assert_eq!(expected_warning, warning.trim());
+        }
+    }
+
+    #[test]
+    fn stow_packages_copies() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/subdirectory".into()],
+            files: vec![(
+                "/home/jdoe/file-1".into(),
+                "golden-files/package-2/file-1".into(),
+            )],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut cmd = Command::new(&mut warnings_output, linker.as_mut()).with_options(Options {
+            copy: true,
+            ..Default::default()
+        });
+        cmd.stow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            vec![
+                state::Link {
+                    package: "package-2".to_string(),
+                    destination: "/home/jdoe/file-1".into(),
+                    source: "/home/jdoe/golden-files/package-2/file-1".into(),
+                    checksum: Some(state::checksum(b"file-1\n")),
+                    hardlink: false,
+                    backup: None,
+                },
+                state::Link {
+                    package: "package-2".to_string(),
+                    destination: "/home/jdoe/subdirectory/file-2".into(),
+                    source: "/home/jdoe/golden-files/package-2/subdirectory/file-2".into(),
+                    checksum: Some(state::checksum(b"file-2\n")),
+                    hardlink: false,
+                    backup: None,
+                },
+            ],
+            cmd.state().links("package-2", "/home/jdoe".as_ref())
+        );
+        drop(cmd);
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/file-1",
+                "rm /home/jdoe/file-1",
+                "cp /home/jdoe/golden-files/package-2/file-1 /home/jdoe/file-1",
+                "cp /home/jdoe/golden-files/package-2/subdirectory/file-2 /home/jdoe/subdirectory/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            "warning: override symlink /home/jdoe/file-1 from golden-files/package-2/file-1 to a file written from /home/jdoe/golden-files/package-2/file-1",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn stow_packages_json_events() {
+        let mut events_output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![(
+                "/home/jdoe/file-1".into(),
+                "golden-files/package-2/file-1".into(),
+            )],
+            ..Default::default()
+        };
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut events_output, &mut linker)
+            .with_options(Options {
+                copy: true,
+                format: event::Format::Json,
+                dry_run: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(events_output.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                r#"{"type":"warning","package":"package-2","destination":"/home/jdoe/file-1","outcome":"overridden","message":"override symlink /home/jdoe/file-1 from golden-files/package-2/file-1 to a file written from /home/jdoe/golden-files/package-2/file-1"}"#,
+                r#"{"type":"delete-file","package":"package-2","destination":"/home/jdoe/file-1","outcome":"planned"}"#,
+                r#"{"type":"copy-file","package":"package-2","source":"/home/jdoe/golden-files/package-2/file-1","destination":"/home/jdoe/file-1","outcome":"planned"}"#,
+                r#"{"type":"create-directory","package":"package-2","destination":"/home/jdoe/subdirectory","outcome":"planned"}"#,
+                r#"{"type":"copy-file","package":"package-2","source":"/home/jdoe/golden-files/package-2/subdirectory/file-2","destination":"/home/jdoe/subdirectory/file-2","outcome":"planned"}"#,
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn stow_packages_json_conflicts() {
+        let mut events_output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            regular_files: vec!["/home/jdoe/file-1".into()],
+            ..Default::default()
+        };
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut events_output, &mut linker)
+            .with_options(Options {
+                format: event::Format::Json,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .unwrap_err();
+
+        assert_eq!(Error::Conflicts(1), err);
+        let output = String::from_utf8(events_output.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                r#"{"type":"create-symlink","package":"package-1","source":"golden-files/package-1/file-2","destination":"/home/jdoe/file-2","outcome":"aborted"}"#,
+                r#"{"type":"conflict","package":"package-1","destination":"/home/jdoe/file-1","outcome":"aborted","message":"existing file"}"#,
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn stow_packages_hardlinks() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![(
+                "/home/jdoe/subdirectory".into(),
+                "golden-files/package-2/subdirectory".into(),
+            )],
+            regular_files: vec!["/home/jdoe/file-1".into()],
+            hardlinks: vec![(
+                "/home/jdoe/file-1".into(),
+                "/home/jdoe/golden-files/package-2/file-1".into(),
+            )],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                hardlink: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/subdirectory",
+                "rm /home/jdoe/subdirectory",
+                "mkdir -p /home/jdoe/subdirectory",
+                "ln /home/jdoe/golden-files/package-2/subdirectory/file-2 /home/jdoe/subdirectory/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_hardlinks_across_filesystems() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            mounts: vec!["/home".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/opt/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                hardlink: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .unwrap_err();
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            Error::CreateHardlink(CreateHardlinkError {
+                source: "/home/jdoe/golden-files/package-1".to_string(),
+                destination: "/opt/jdoe".to_string(),
+                reason: linker::CROSS_DEVICE_REASON.to_string(),
+            }),
+            err
+        );
+        assert_eq!("", output);
+    }
+
+    #[test]
+    fn stow_package_subpaths_through_folded_directory() {
+        let ctx = linker::tests::TestWithTempDir::new("folded-subpath");
+        std::fs::create_dir_all(ctx.dir.join("dots/nvim/.config/nvim")).unwrap();
+        std::fs::write(
+            ctx.dir.join("dots/nvim/.config/nvim/init.lua"),
+            "set number",
+        )
+        .unwrap();
+        std::fs::create_dir_all(ctx.dir.join("home")).unwrap();
+
+        let root_src = ctx.dir.join("dots");
+        let root_dest = ctx.dir.join("home");
+        let src: path::Source = root_src.as_path().into();
+        let dest: path::Destination = root_dest.as_path().into();
+        let mut output = Vec::new();
+        let mut linker = linker::Filesystem;
+
+        Command::new(&mut output, &mut linker)
+            .stow(&src, &dest, vec!["nvim".to_string()])
+            .expect("shouldn't fail");
+        assert_eq!(
+            std::path::PathBuf::from("../dots/nvim/.config"),
+            std::fs::read_link(ctx.dir.join("home/.config")).unwrap()
+        );
+
+        // the package file is reached through the folded directory
+        Command::new(&mut output, &mut linker)
+            .stow(&src, &dest, vec!["nvim/.config/nvim".to_string()])
+            .expect("shouldn't fail");
+        Command::new(&mut output, &mut linker)
+            .unstow(&src, &dest, vec!["nvim/.config/nvim".to_string()])
+            .expect("shouldn't fail");
+        let err = Command::new(&mut output, &mut linker)
+            .with_options(Options {
+                copy: true,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["nvim/.config/nvim".to_string()])
+            .unwrap_err();
+
+        let init = ctx.dir.join("dots/nvim/.config/nvim/init.lua");
+        assert!(std::fs::symlink_metadata(&init).unwrap().is_file());
+        assert_eq!("set number", std::fs::read_to_string(&init).unwrap());
+        assert_eq!(Error::Conflicts(1), err);
+
+        let destination = ctx.dir.join("home/.config/nvim/init.lua");
+        assert_eq!(
+            vec![
+                format!(
+                    "warning: skip {}: linked through a parent directory",
+                    destination.display()
+                ),
+                format!(
+                    "conflict: {} (nvim): package file reached through a linked directory, aborted",
+                    destination.display()
+                ),
+            ],
+            String::from_utf8(output)
+                .unwrap()
+                .trim()
+                .split('\n')
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn stow_packages_sharing_directory() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .stow(
+                &src,
+                &dest,
+                vec!["package-2".to_string(), "package-3".to_string()],
+            )
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "ln -s golden-files/package-2/file-1 /home/jdoe/file-1",
+                "mkdir -p /home/jdoe/subdirectory",
+                "ln -s ../golden-files/package-2/subdirectory/file-2 /home/jdoe/subdirectory/file-2",
+                "ln -s ../golden-files/package-3/subdirectory/file-3 /home/jdoe/subdirectory/file-3",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_unfold_directory() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![(
+                "/home/jdoe/subdirectory".into(),
+                "golden-files/package-2/subdirectory".into(),
+            )],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .stow(&src, &dest, vec!["package-3".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/subdirectory",
+                "rm /home/jdoe/subdirectory",
+                "mkdir -p /home/jdoe/subdirectory",
+                "ln -s ../golden-files/package-2/subdirectory/file-2 /home/jdoe/subdirectory/file-2",
+                "ln -s ../golden-files/package-3/subdirectory/file-3 /home/jdoe/subdirectory/file-3",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim());
+    }
+
+    #[test]
+    fn stow_packages_with_conflicts() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/file-2".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, linker.as_mut())
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .unwrap_err();
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(Error::Conflicts(1), err);
+        assert_eq!("", output);
+        assert_eq!(
+            "conflict: /home/jdoe/file-2 (package-1): existing directory, aborted",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn stow_packages_abort_on_existing_file() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            regular_files: vec!["/home/jdoe/file-1".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, linker.as_mut())
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .unwrap_err();
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(Error::Conflicts(1), err);
+        assert_eq!("", output);
+        assert_eq!(
+            "conflict: /home/jdoe/file-1 (package-1): existing file, aborted",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn stow_packages_conflict_policies() {
+        let testcases = vec![
+            (
+                plan::ConflictPolicy::Skip,
+                vec![],
+                vec![
+                    "conflict: /home/jdoe/file-1 (package-1): existing file, skipped",
+                    "conflict: /home/jdoe/file-2 (package-1): existing symlink to /etc/file-2, skipped",
+                ],
+            ),
+            (
+                plan::ConflictPolicy::Overwrite,
+                vec![
+                    "rm /home/jdoe/file-1",
+                    "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
+                    "rm /home/jdoe/file-2",
+                    "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+                ],
+                vec![
+                    "conflict: /home/jdoe/file-1 (package-1): existing file, overwritten",
+                    "conflict: /home/jdoe/file-2 (package-1): existing symlink to /etc/file-2, overwritten",
+                ],
+            ),
+            (
+                plan::ConflictPolicy::Backup,
+                vec![
+                    "mkdir -p /home/jdoe/.stow-backup/package-1",
+                    "mv /home/jdoe/file-1 /home/jdoe/.stow-backup/package-1/file-1",
+                    "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
+                    "mv /home/jdoe/file-2 /home/jdoe/.stow-backup/package-1/file-2",
+                    "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+                ],
+                vec![
+                    "conflict: /home/jdoe/file-1 (package-1): existing file, moved to /home/jdoe/.stow-backup/package-1/file-1, backed up",
+                    "conflict: /home/jdoe/file-2 (package-1): existing symlink to /etc/file-2, moved to /home/jdoe/.stow-backup/package-1/file-2, backed up",
+                ],
+            ),
+        ];
+
+        for (policy, expected_output, expected_warning) in testcases {
+            let mut commands_output = std::io::BufWriter::new(Vec::new());
+            let mut warnings_output = std::io::BufWriter::new(Vec::new());
+            let noop = linker::Noop {
+                current_directory: "/home/jdoe".into(),
+                directories: vec!["/home/jdoe".into()],
+                files: vec![("/home/jdoe/file-2".into(), "/etc/file-2".into())],
+                regular_files: vec!["/home/jdoe/file-1".into()],
+                ..Default::default()
+            };
+            let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+            let src: path::Source = "golden-files".into();
+            let dest: path::Destination = "/home/jdoe".into();
+
+            Command::new(&mut warnings_output, linker.as_mut())
+                .with_options(Options {
+                    conflict_policy: policy,
+                    ..Default::default()
+                })
+                .stow(&src, &dest, vec!["package-1".to_string()])
+                .expect("shouldn't fail");
+
+            let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+            let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+            let mut expected_output = expected_output.clone();
+            expected_output.insert(0, "readlink /home/jdoe/file-2");
+            assert_eq!(
+                expected_output,
+                output.trim().split('\n').collect::<Vec<&str>>(),
+                "unexpected commands with policy {:?}",
+                policy
+            );
+            assert_eq!(
+                expected_warning,
+                warning.trim().split('\n').collect::<Vec<&str>>(),
+                "unexpected warnings with policy {:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn stow_packages_adopt_files() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            regular_files: vec!["/home/jdoe/file-1".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                conflict_policy: plan::ConflictPolicy::Adopt,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "mv /home/jdoe/file-1 /home/jdoe/golden-files/package-1/file-1",
+                "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
+                "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            "conflict: /home/jdoe/file-1 (package-1): existing file, moved to /home/jdoe/golden-files/package-1/file-1, adopted",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn stow_packages_adopt_symlink() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![("/home/jdoe/file-1".into(), "/etc/file-1".into())],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                conflict_policy: plan::ConflictPolicy::Adopt,
+                ..Default::default()
+            })
+            .stow(&src, &dest, vec!["package-1".to_string()])
+            .unwrap_err();
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(Error::Conflicts(1), err);
+        assert_eq!("readlink /home/jdoe/file-1", output.trim());
+        assert_eq!(
+            "conflict: /home/jdoe/file-1 (package-1): existing symlink to /etc/file-1, symlinks cannot be adopted, aborted",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn unstow_packages() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/subdirectory".into()],
+            files: vec![
+                (
+                    "/home/jdoe/file-other".into(),
+                    "golden-files/package-3/file-other".into(),
+                ),
+                (
+                    "/home/jdoe/subdirectory/file-2".into(),
+                    "../golden-files/package-2/subdirectory/file-2".into(),
+                ),
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-1/file-1".into(),
+                ),
+                (
+                    "/home/jdoe/file-2".into(),
+                    "/home/jdoe/golden-files/package-2/file-2".into(),
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .unstow(
+                &src,
+                &dest,
+                vec!["package-1".to_string(), "package-2".to_string()],
+            )
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                // plan package1
+                "readlink /home/jdoe/file-1",
+                "readlink /home/jdoe/file-2",
+                // plan package2
+                "readlink /home/jdoe/subdirectory/file-2",
+                // execute
+                "rm /home/jdoe/file-1",
+                "rm /home/jdoe/subdirectory/file-2",
+                "rmdir /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            "warning: skip /home/jdoe/file-2: links to /home/jdoe/golden-files/package-2/file-2 instead of /home/jdoe/golden-files/package-1/file-2",
+            warning.trim()
+        )
+    }
+
+    #[test]
+    fn unstow_packages_dotfiles() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.config".into(),
+                "/home/jdoe/.config/nvim".into(),
+            ],
+            files: vec![
+                (
+                    "/home/jdoe/.zshrc".into(),
+                    "golden-files/package-5/dot-zshrc".into(),
+                ),
+                (
+                    "/home/jdoe/.config/nvim/init.lua".into(),
+                    "../../golden-files/package-5/dot-config/nvim/init.lua".into(),
+                ),
+            ],
+            regular_files: vec!["/home/jdoe/.config/user-dirs.dirs".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                dotfiles: true,
+                ..Default::default()
+            })
+            .unstow(&src, &dest, vec!["package-5".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/.config/nvim/init.lua",
+                "readlink /home/jdoe/.zshrc",
+                "rm /home/jdoe/.config/nvim/init.lua",
+                "rm /home/jdoe/.zshrc",
+                "rmdir /home/jdoe/.config/nvim",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn unstow_keeps_directories_reached_through_symlinks() {
+        let ctx = linker::tests::TestWithTempDir::new("empty-directories");
+        std::fs::create_dir_all(ctx.dir.join("dots/nvim/.config/nvim")).unwrap();
+        std::fs::write(ctx.dir.join("dots/nvim/.config/nvim/init.lua"), "").unwrap();
+        std::fs::create_dir_all(ctx.dir.join("elsewhere/sub")).unwrap();
+        std::os::unix::fs::symlink("file", ctx.dir.join("elsewhere/sub/link")).unwrap();
+        std::fs::create_dir_all(ctx.dir.join("home/real")).unwrap();
+        std::os::unix::fs::symlink("file", ctx.dir.join("home/real/link")).unwrap();
+        std::os::unix::fs::symlink("../dots/nvim/.config", ctx.dir.join("home/.config")).unwrap();
+        std::os::unix::fs::symlink("../elsewhere", ctx.dir.join("home/outside")).unwrap();
+
+        let root_src = ctx.dir.join("dots");
+        let root_dest = ctx.dir.join("home");
+        let mut plan = plan::Plan::default();
+        for file in [".config/nvim/init.lua", "outside/sub/link", "real/link"] {
+            plan.push("nvim", plan::Operation::DeleteFile(root_dest.join(file)));
+        }
+
+        let mut linker = linker::Filesystem;
+        Command::new(Vec::new(), &mut linker)
+            .plan_remove_empty_directories(
+                &mut plan,
+                &root_src.as_path().into(),
+                &root_dest.as_path().into(),
+            )
+            .expect("shouldn't fail");
+
+        let removed = plan
+            .actions
+            .iter()
+            .filter_map(|action| match &action.operation {
+                plan::Operation::RemoveDirectory(directory) => Some(directory.clone()),
+                _ => None,
+            })
+            .collect::<Vec<std::path::PathBuf>>();
+        assert_eq!(vec![root_dest.join("real")], removed);
+    }
+
+    #[test]
+    fn stow_each_package() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            regular_files: vec!["/home/jdoe/file-2".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, linker.as_mut())
+            .for_each_package(
+                vec!["package-1".to_string(), "package-3".to_string()],
+                |cmd, packages| cmd.stow(&src, &dest, packages),
+            )
+            .unwrap_err();
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(Error::FailedPackages(1), err);
+        assert_eq!(
+            vec!["ln -s golden-files/package-3/subdirectory /home/jdoe/subdirectory"],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            vec![
+                "conflict: /home/jdoe/file-2 (package-1): existing file, aborted",
+                "package: package-1 failed: 1 conflict(s) detected, nothing has been changed",
+                "package: package-3 ok",
+            ],
+            warning.trim().split('\n').collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn status_packages() {
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-1/file-1".into(),
+                ),
+                (
+                    "/home/jdoe/subdirectory".into(),
+                    "golden-files/package-3/subdirectory".into(),
+                ),
+                (
+                    "/home/jdoe/file-4".into(),
+                    "golden-files/package-4/old-file-4".into(),
+                ),
+            ],
+            regular_files: vec![
+                "/home/jdoe/file-2".into(),
+                "/home/jdoe/golden-files/package-1/file-1".into(),
+            ],
+            ..Default::default()
+        };
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let packages = vec![
+            "package-1".to_string(),
+            "package-2".to_string(),
+            "package-3".to_string(),
+            "package-4".to_string(),
+        ];
+        let report = Command::new(&mut warnings_output, &mut linker)
+            .status(&src, &dest, &packages)
+            .expect("shouldn't fail");
+
+        let entry = |package: &str, destination: &str, state| status::Entry {
+            package: package.to_string(),
+            destination: destination.into(),
+            state,
+        };
+        assert_eq!(
+            vec![
+                entry("package-1", "/home/jdoe/file-1", status::State::Linked),
+                entry("package-1", "/home/jdoe/file-2", status::State::Blocked),
+                entry(
+                    "package-2",
+                    "/home/jdoe/file-1",
+                    status::State::Elsewhere("/home/jdoe/golden-files/package-1/file-1".into())
+                ),
+                entry(
+                    "package-2",
+                    "/home/jdoe/subdirectory/file-2",
+                    status::State::Elsewhere(
+                        "/home/jdoe/golden-files/package-3/subdirectory/file-2".into()
+                    )
+                ),
+                entry(
+                    "package-3",
+                    "/home/jdoe/subdirectory/file-3",
+                    status::State::Linked
+                ),
+                entry(
+                    "package-4",
+                    "/home/jdoe/file-4",
+                    status::State::Broken("/home/jdoe/golden-files/package-4/old-file-4".into())
+                ),
+                entry(
+                    "package-4",
+                    "/home/jdoe/subdirectory/file-4",
+                    status::State::Elsewhere(
+                        "/home/jdoe/golden-files/package-3/subdirectory/file-4".into()
+                    )
+                ),
+            ],
+            report.entries
+        );
+        assert_eq!(packages, report.packages);
+    }
+
+    #[test]
+    fn prune_dangling_links() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.config".into(),
+                "/home/jdoe/.config/nvim".into(),
+                "/home/jdoe/golden-files".into(),
+            ],
+            files: vec![
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-1/file-1".into(),
+                ),
+                ("/home/jdoe/.zshrc".into(), "golden-files/zsh/zshrc".into()),
+                ("/home/jdoe/.vimrc".into(), "/opt/vim/vimrc".into()),
+                (
+                    "/home/jdoe/.config/git".into(),
+                    "../golden-files/git/config".into(),
+                ),
+                (
+                    "/home/jdoe/.config/nvim/init.lua".into(),
+                    "../../golden-files/nvim/init.lua".into(),
+                ),
+                ("/home/jdoe/golden-files/link".into(), "missing-file".into()),
+            ],
+            regular_files: vec!["/home/jdoe/golden-files/package-1/file-1".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .prune(&src, &dest, 1)
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/file-1",
+                "readlink /home/jdoe/.zshrc",
+                "readlink /home/jdoe/.vimrc",
+                "readlink /home/jdoe/.config/git",
+                "rm /home/jdoe/.zshrc",
+                "rm /home/jdoe/.config/git",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            vec![
+                "prune: /home/jdoe/.zshrc (broken link to /home/jdoe/golden-files/zsh/zshrc)",
+                "prune: /home/jdoe/.config/git (broken link to /home/jdoe/golden-files/git/config)",
+            ],
+            warning.trim().split('\n').collect::<Vec<&str>>(),
+        );
+    }
+
+    #[test]
+    fn prune_unreadable_directories() {
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.config".into(),
+                "/home/jdoe/.local".into(),
+                "/home/jdoe/.local/containers".into(),
+            ],
+            files: vec![
+                ("/home/jdoe/.zshrc".into(), "golden-files/zsh/zshrc".into()),
+                (
+                    "/home/jdoe/.config/git".into(),
+                    "../golden-files/git/config".into(),
+                ),
+            ],
+            unreadable: vec![
+                "/home/jdoe/.local/containers".into(),
+                "/home/jdoe/.config".into(),
+            ],
+            ..Default::default()
+        };
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let plan = Command::new(&mut warnings_output, &mut linker)
+            .plan_prune(&src, &dest, 5)
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            vec![plan::Action {
+                package: "zsh".to_string(),
+                operation: plan::Operation::DeleteFile("/home/jdoe/.zshrc".into()),
+            }],
+            plan.actions
+        );
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                "prune: /home/jdoe/.zshrc (broken link to /home/jdoe/golden-files/zsh/zshrc)",
+                "warning: skip /home/jdoe/.config: permission denied",
+                "warning: skip /home/jdoe/.local/containers: permission denied",
+            ],
+            warning.trim().split('\n').collect::<Vec<&str>>(),
+        );
+    }
+
+    #[test]
+    fn prune_unreadable_target_directory() {
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            unreadable: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        assert_eq!(
+            Error::ReadFile(ReadFileError {
+                file: "/home/jdoe".to_string(),
+                reason: "permission denied".to_string(),
+            }),
+            Command::new(crate::writer::Noop, &mut linker)
+                .plan_prune(&src, &dest, 5)
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn unstow_packages_broken_links() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/subdirectory".into()],
+            files: vec![
+                ("/home/jdoe/file-other".into(), "/opt/missing/file".into()),
+                (
+                    "/home/jdoe/file-old".into(),
+                    "golden-files/package-2/file-old".into(),
+                ),
+                (
+                    "/home/jdoe/subdirectory/file-2".into(),
+                    "../golden-files/./package-2/subdirectory/../subdirectory/file-2".into(),
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .unstow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/subdirectory/file-2",
+                "rm /home/jdoe/subdirectory/file-2",
+                "rmdir /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn unstow_deleted_package() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![
+                ("/home/jdoe/.zshrc".into(), "golden-files/zsh/zshrc".into()),
+                ("/home/jdoe/.zshenv".into(), "/opt/zsh/zshenv".into()),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let mut recorded = state::State::default();
+        for (destination, source) in [
+            ("/home/jdoe/.zshrc", "golden-files/zsh/zshrc"),
+            ("/home/jdoe/.zshenv", "golden-files/zsh/zshenv"),
+            ("/home/jdoe/.zprofile", "golden-files/zsh/zprofile"),
+        ] {
+            recorded.record(&plan::Action {
+                package: "zsh".to_string(),
+                operation: plan::Operation::CreateSymlink {
+                    source: source.into(),
+                    destination: destination.into(),
+                },
+            });
+        }
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut cmd = Command::new(&mut warnings_output, linker.as_mut()).with_state(recorded);
+        cmd.unstow(&src, &dest, vec!["zsh".to_string()])
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            vec![state::Link {
+                package: "zsh".to_string(),
+                destination: "/home/jdoe/.zshenv".into(),
+                source: "golden-files/zsh/zshenv".into(),
+                checksum: None,
+                hardlink: false,
+                backup: None,
+            }],
+            cmd.state().links("zsh", "/home/jdoe".as_ref())
+        );
+        drop(cmd);
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/.zshrc",
+                "readlink /home/jdoe/.zshenv",
+                "rm /home/jdoe/.zshrc",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            vec!["warning: skip /home/jdoe/.zshenv: recorded as a link to golden-files/zsh/zshenv but links to /opt/zsh/zshenv"],
+            warning.trim().split('\n').collect::<Vec<&str>>(),
+        );
+    }
+
+    #[test]
+    fn unstow_forgets_missing_links_once_executed() {
+        let mut linker = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            ..Default::default()
+        };
+
+        let mut recorded = state::State::default();
+        recorded.record(&plan::Action {
+            package: "zsh".to_string(),
+            operation: plan::Operation::CreateSymlink {
+                source: "golden-files/zsh/zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        });
+        let link = recorded.links("zsh", "/home/jdoe".as_ref());
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut cmd = Command::new(crate::writer::Noop, &mut linker).with_state(recorded);
+        let mut plan = cmd
+            .plan_unstow(&src, &dest, &["zsh".to_string()])
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            vec![std::path::PathBuf::from("/home/jdoe/.zshrc")],
+            plan.forgotten
+        );
+        assert_eq!(link, cmd.state().links("zsh", "/home/jdoe".as_ref()));
+
+        plan.conflict(
+            "zsh",
+            "/home/jdoe/.zshenv".as_ref(),
+            "existing file".to_string(),
+            plan::ConflictPolicy::Abort,
+        );
+        assert_eq!(Err(Error::Conflicts(1)), cmd.execute(&plan));
+        assert_eq!(link, cmd.state().links("zsh", "/home/jdoe".as_ref()));
+
+        plan.conflicts.clear();
+        cmd.execute(&plan).expect("shouldn't fail");
+        assert!(cmd.state().links("zsh", "/home/jdoe".as_ref()).is_empty());
+    }
+
+    #[test]
+    fn unstow_packages_written_files() {
+        let rendered = "[user]\n\tname = jdoe\n";
+        let hook = "#!/bin/sh\nexit 0\n";
+        let testcases = vec![
+            (
+                rendered,
+                hook,
+                vec![
+                    "rm /home/jdoe/git/config",
+                    "rm /home/jdoe/git/hooks/pre-commit",
+                    "rmdir /home/jdoe/git/hooks",
+                    "rmdir /home/jdoe/git",
+                ],
+                vec![""],
+            ),
+            (
+                "[user]\n\tname = john\n",
+                "#!/bin/sh\nexit 1\n",
+                vec![""],
+                vec![
+                    "warning: skip /home/jdoe/git/config: modified since it was written",
+                    "warning: skip /home/jdoe/git/hooks/pre-commit: modified since it was written",
+                ],
+            ),
+        ];
+
+        for (config, pre_commit, expected_output, expected_warnings) in testcases {
+            let mut commands_output = std::io::BufWriter::new(Vec::new());
+            let mut warnings_output = std::io::BufWriter::new(Vec::new());
+            let noop = linker::Noop {
+                current_directory: "/home/jdoe".into(),
+                directories: vec![
+                    "/home/jdoe".into(),
+                    "/home/jdoe/git".into(),
+                    "/home/jdoe/git/hooks".into(),
+                ],
+                regular_files: vec![
+                    "/home/jdoe/git/config".into(),
+                    "/home/jdoe/git/hooks/pre-commit".into(),
+                ],
+                contents: vec![
+                    ("/home/jdoe/git/config".into(), config.as_bytes().to_vec()),
+                    (
+                        "/home/jdoe/git/hooks/pre-commit".into(),
+                        pre_commit.as_bytes().to_vec(),
+                    ),
+                ],
+                ..Default::default()
+            };
+            let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+            let mut recorded = state::State::default();
+            recorded.record(&plan::Action {
+                package: "package-7".to_string(),
+                operation: plan::Operation::WriteFile {
+                    source: "/home/jdoe/golden-files/package-7/git/config.tmpl".into(),
+                    destination: "/home/jdoe/git/config".into(),
+                    content: rendered.to_string(),
+                },
+            });
+            recorded.record(&plan::Action {
+                package: "package-7".to_string(),
+                operation: plan::Operation::CopyFile {
+                    source: "/home/jdoe/golden-files/package-7/git/hooks/pre-commit".into(),
+                    destination: "/home/jdoe/git/hooks/pre-commit".into(),
+                    checksum: state::checksum(hook.as_bytes()),
+                },
+            });
+
+            let src: path::Source = "golden-files".into();
+            let dest: path::Destination = "/home/jdoe".into();
+
+            Command::new(&mut warnings_output, linker.as_mut())
+                .with_state(recorded)
+                .unstow(&src, &dest, vec!["package-7".to_string()])
+                .expect("shouldn't fail");
+
+            let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+            let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+            assert_eq!(
+                expected_output,
+                output.trim().split('\n').collect::<Vec<&str>>()
+            );
+            assert_eq!(
+                expected_warnings,
+                warning.trim().split('\n').collect::<Vec<&str>>()
+            );
+        }
+    }
+
+    #[test]
+    fn unstow_forgets_missing_files_once_executed() {
+        let testcases = vec![
+            plan::Operation::WriteFile {
+                source: "/home/jdoe/golden-files/package-7/git/config.tmpl".into(),
+                destination: "/home/jdoe/git/config".into(),
+                content: "[user]\n\tname = jdoe\n".to_string(),
+            },
+            plan::Operation::CopyFile {
+                source: "/home/jdoe/golden-files/package-7/git/hooks/pre-commit".into(),
+                destination: "/home/jdoe/git/hooks/pre-commit".into(),
+                checksum: state::checksum(b"#!/bin/sh\nexit 0\n"),
+            },
+            plan::Operation::CreateHardlink {
+                source: "/home/jdoe/golden-files/package-7/git/ignore".into(),
+                destination: "/home/jdoe/git/ignore".into(),
+            },
+        ];
+
+        for operation in testcases {
+            let mut linker = linker::Noop {
+                current_directory: "/home/jdoe".into(),
+                directories: vec!["/home/jdoe".into()],
+                ..Default::default()
+            };
+
+            let mut recorded = state::State::default();
+            recorded.record(&plan::Action {
+                package: "package-7".to_string(),
+                operation: operation.clone(),
+            });
+            let link = recorded.links("package-7", "/home/jdoe".as_ref());
+
+            let src: path::Source = "golden-files".into();
+            let dest: path::Destination = "/home/jdoe".into();
+
+            let mut cmd = Command::new(crate::writer::Noop, &mut linker).with_state(recorded);
+            let plan = cmd
+                .plan_unstow(&src, &dest, &["package-7".to_string()])
+                .expect("shouldn't fail");
+
+            assert_eq!(
+                vec![link[0].destination.clone()],
+                plan.forgotten,
+                "operation {:?}",
+                operation
+            );
+            assert_eq!(link, cmd.state().links("package-7", "/home/jdoe".as_ref()));
+
+            cmd.execute(&plan).expect("shouldn't fail");
+            assert!(cmd
+                .state()
+                .links("package-7", "/home/jdoe".as_ref())
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn unstow_packages_hardlinks() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/subdirectory".into()],
+            regular_files: vec![
+                "/home/jdoe/file-1".into(),
+                "/home/jdoe/subdirectory/file-2".into(),
+            ],
+            hardlinks: vec![(
+                "/home/jdoe/file-1".into(),
+                "/home/jdoe/golden-files/package-2/file-1".into(),
+            )],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let mut recorded = state::State::default();
+        for file in ["file-1", "subdirectory/file-2"] {
+            recorded.record(&plan::Action {
+                package: "package-2".to_string(),
+                operation: plan::Operation::CreateHardlink {
+                    source: format!("/home/jdoe/golden-files/package-2/{}", file).into(),
+                    destination: format!("/home/jdoe/{}", file).into(),
+                },
+            });
+        }
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_state(recorded)
+            .unstow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec!["rm /home/jdoe/file-1"],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!(
+            "warning: skip /home/jdoe/subdirectory/file-2: not a hardlink to /home/jdoe/golden-files/package-2/subdirectory/file-2 anymore",
+            warning.trim()
+        );
+    }
+
+    #[test]
+    fn unstow_unknown_package() {
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Noop::default();
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let err = Command::new(&mut warnings_output, &mut linker)
+            .unstow(&src, &dest, vec!["zsh".to_string()])
+            .unwrap_err();
+
+        assert_eq!(Error::PackageNotFound("zsh".to_string()), err);
+    }
+
+    #[test]
+    fn restow_packages() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-2/file-1".into(),
+                ),
+                (
+                    "/home/jdoe/file-3".into(),
+                    "golden-files/package-2/file-3".into(),
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .restow(&src, &dest, vec!["package-2".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                // plan unstow
+                "readlink /home/jdoe/file-1",
+                "readlink /home/jdoe/file-3",
+                // plan stow
+                "readlink /home/jdoe/file-1",
+                // execute
+                "rm /home/jdoe/file-3",
+                "ln -s golden-files/package-2/subdirectory /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn apply_profiles() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-1/file-1".into(),
+                ),
+                (
+                    "/home/jdoe/file-2".into(),
+                    "golden-files/package-1/file-2".into(),
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut recorded = state::State::default();
+        recorded.apply(dest.as_ref(), "old", &["package-1".to_string()]);
+
+        let mut command = Command::new(&mut warnings_output, linker.as_mut()).with_state(recorded);
+        command
+            .apply_profile(&src, &dest, "new", vec!["package-3".to_string()])
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            Some(&state::Profile {
+                target: "/home/jdoe".into(),
+                name: "new".to_string(),
+                packages: vec!["package-3".to_string()],
+            }),
+            command.state().profile(dest.as_ref())
+        );
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                // plan unstow old profile
+                "readlink /home/jdoe/file-1",
+                "readlink /home/jdoe/file-2",
+                // execute
+                "rm /home/jdoe/file-1",
+                "rm /home/jdoe/file-2",
+                "ln -s golden-files/package-3/subdirectory /home/jdoe/subdirectory",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>()
+        );
+        assert_eq!("", warning.trim())
+    }
+
+    #[test]
+    fn unstow_packages_restore_backups() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec!["/home/jdoe".into()],
+            files: vec![
+                (
+                    "/home/jdoe/file-1".into(),
+                    "golden-files/package-1/file-1".into(),
+                ),
+                (
+                    "/home/jdoe/file-2".into(),
+                    "golden-files/package-1/file-2".into(),
+                ),
+            ],
+            regular_files: vec![
+                "/home/jdoe/.stow-backup/package-1/file-1".into(),
+                "/home/jdoe/.stow-backup/package-1/file-1.1".into(),
+                "/home/jdoe/.stow-backup/package-1/file-2".into(),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        // only file-1 was backed up, file-2 being found in the backup
+        // directory by chance
+        let mut recorded = state::State::default();
+        for operation in [
+            plan::Operation::Backup {
+                source: "/home/jdoe/file-1".into(),
+                destination: "/home/jdoe/.stow-backup/package-1/file-1.1".into(),
+            },
+            plan::Operation::CreateSymlink {
+                source: "golden-files/package-1/file-1".into(),
+                destination: "/home/jdoe/file-1".into(),
+            },
+            plan::Operation::CreateSymlink {
+                source: "golden-files/package-1/file-2".into(),
+                destination: "/home/jdoe/file-2".into(),
+            },
+        ] {
+            recorded.record(&plan::Action {
+                package: "package-1".to_string(),
+                operation,
+            });
+        }
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut cmd = Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                restore_backups: true,
+                ..Default::default()
+            })
+            .with_state(recorded);
+        cmd.unstow(&src, &dest, vec!["package-1".to_string()])
+            .expect("shouldn't fail");
+        assert!(cmd
+            .state()
+            .links("package-1", "/home/jdoe".as_ref())
+            .is_empty());
+        drop(cmd);
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        let warning = String::from_utf8(warnings_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/file-1",
+                "readlink /home/jdoe/file-2",
+                "rm /home/jdoe/file-1",
+                "mv /home/jdoe/.stow-backup/package-1/file-1.1 /home/jdoe/file-1",
+                "rm /home/jdoe/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+        assert_eq!(
+            "restore: /home/jdoe/file-1 from /home/jdoe/.stow-backup/package-1/file-1.1",
+            warning.trim()
+        )
+    }
+
+    #[test]
+    fn unstow_packages_remove_backup_directories() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let mut warnings_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.stow-backup".into(),
+                "/home/jdoe/.stow-backup/package-1".into(),
+            ],
+            files: vec![(
+                "/home/jdoe/file-1".into(),
+                "golden-files/package-1/file-1".into(),
+            )],
+            regular_files: vec!["/home/jdoe/.stow-backup/package-1/file-1".into()],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let mut recorded = state::State::default();
+        for operation in [
+            plan::Operation::Backup {
+                source: "/home/jdoe/file-1".into(),
+                destination: "/home/jdoe/.stow-backup/package-1/file-1".into(),
+            },
+            plan::Operation::CreateSymlink {
+                source: "golden-files/package-1/file-1".into(),
+                destination: "/home/jdoe/file-1".into(),
+            },
+        ] {
+            recorded.record(&plan::Action {
+                package: "package-1".to_string(),
+                operation,
+            });
+        }
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        Command::new(&mut warnings_output, linker.as_mut())
+            .with_options(Options {
+                restore_backups: true,
+                ..Default::default()
+            })
+            .with_state(recorded)
+            .unstow(&src, &dest, vec!["package-1".to_string()])
+            .expect("shouldn't fail");
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            vec![
+                "readlink /home/jdoe/file-1",
+                "rm /home/jdoe/file-1",
+                "mv /home/jdoe/.stow-backup/package-1/file-1 /home/jdoe/file-1",
+                "rmdir /home/jdoe/.stow-backup/package-1",
+                "rmdir /home/jdoe/.stow-backup",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
+    }
+
+    #[test]
+    fn stow_packages_record_backups() {
+        let mut commands_output = std::io::BufWriter::new(Vec::new());
+        let noop = linker::Noop {
+            current_directory: "/home/jdoe".into(),
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.stow-backup".into(),
+                "/home/jdoe/.stow-backup/package-1".into(),
+            ],
+            regular_files: vec![
+                "/home/jdoe/file-1".into(),
+                "/home/jdoe/.stow-backup/package-1/file-1".into(),
+            ],
+            ..Default::default()
+        };
+        let mut linker = Box::new(linker::Verbose::new(&mut commands_output, noop));
+
+        let src: path::Source = "golden-files".into();
+        let dest: path::Destination = "/home/jdoe".into();
+
+        let mut cmd = Command::new(crate::writer::Noop, linker.as_mut()).with_options(Options {
+            conflict_policy: plan::ConflictPolicy::Backup,
+            ..Default::default()
+        });
+        cmd.stow(&src, &dest, vec!["package-1".to_string()])
+            .expect("shouldn't fail");
+
+        assert_eq!(
+            Some(std::path::PathBuf::from(
+                "/home/jdoe/.stow-backup/package-1/file-1.1"
+            )),
+            cmd.state()
+                .link("/home/jdoe/file-1".as_ref())
+                .and_then(|l| l.backup.clone())
+        );
+        assert_eq!(
+            None,
+            cmd.state()
+                .link("/home/jdoe/file-2".as_ref())
+                .and_then(|l| l.backup.clone())
+        );
+        drop(cmd);
+
+        let output = String::from_utf8(commands_output.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                "mv /home/jdoe/file-1 /home/jdoe/.stow-backup/package-1/file-1.1",
+                "ln -s golden-files/package-1/file-1 /home/jdoe/file-1",
+                "ln -s golden-files/package-1/file-2 /home/jdoe/file-2",
+            ],
+            output.trim().split('\n').collect::<Vec<&str>>(),
+        );
     }
 }
@@ -0,0 +1,309 @@
+use crate::{ignore, path, plan, Error, ReadFileError};
+
+/// Name of the configuration file read from the stow directory.
+pub const LOCAL_CONFIG_FILE: &str = ".stowrc";
+
+/// Path of the configuration file read from the home directory.
+pub const GLOBAL_CONFIG_FILE: &str = ".config/stow/config.toml";
+
+/// Prefix of a package argument naming a group of packages, e.g. `@work`.
+pub const GROUP_PREFIX: &str = "@";
+
+/// Default values of the command line options, read from TOML files.
+///
+/// Relative paths are resolved from the directory containing the
+/// configuration file, and a leading `~/` from the home directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Stow directory. Only read from the global configuration, since the
+    /// local one is found in the stow directory.
+    pub source: Option<std::path::PathBuf>,
+    pub target: Option<std::path::PathBuf>,
+    pub verbosity: Option<u8>,
+    pub conflict: Option<plan::ConflictPolicy>,
+    /// Patterns of the files to ignore in every package.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Tags selecting the variants of the package files.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Named lists of packages, which can be used in place of a package.
+    #[serde(default)]
+    pub groups: std::collections::BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub profiles: std::collections::BTreeMap<String, Profile>,
+}
+
+/// Set of packages to apply at once on a machine, selected with its name.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Packages of the profile, which can be package groups.
+    pub packages: Vec<String>,
+    /// Tags selecting the variants of the package files, on top of the ones
+    /// of the configuration.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Target directory overriding the one of the configuration.
+    pub target: Option<std::path::PathBuf>,
+}
+
+impl Config {
+    /// Reads the configuration stored in `file`, starting from an empty
+    /// configuration when the file doesn't exist or is a GNU stow `.stowrc`
+    /// file.
+    pub fn load(file: &std::path::Path, home: Option<&std::path::Path>) -> Result<Self, Error> {
+        let read_error = |reason: String| {
+            Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason,
+            })
+        };
+
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(read_error(e.to_string())),
+        };
+        if is_gnu_stowrc(&content) {
+            return Ok(Self::default());
+        }
+
+        let mut config: Self = toml::from_str(&content).map_err(|e| read_error(e.to_string()))?;
+        let directory = file.parent().unwrap_or_else(|| std::path::Path::new(""));
+        config.source = config.source.map(|p| resolve(directory, home, &p));
+        config.target = config.target.map(|p| resolve(directory, home, &p));
+        for profile in config.profiles.values_mut() {
+            profile.target = profile.target.take().map(|p| resolve(directory, home, &p));
+        }
+
+        Ok(config)
+    }
+
+    /// Overrides the values of the configuration with the ones defined in
+    /// `other`. Ignore patterns and tags are added up.
+    pub fn merge(mut self, other: Config) -> Self {
+        self.source = other.source.or(self.source);
+        self.target = other.target.or(self.target);
+        self.verbosity = other.verbosity.or(self.verbosity);
+        self.conflict = other.conflict.or(self.conflict);
+        self.ignore.extend(other.ignore);
+        self.tags.extend(other.tags);
+        self.groups.extend(other.groups);
+        self.profiles.extend(other.profiles);
+        self
+    }
+
+    /// Finds the profile called `name`.
+    pub fn profile(&self, name: &str) -> Result<&Profile, Error> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| Error::Generic(format!("unknown profile {}", name)))
+    }
+
+    /// Builds the set of ignored patterns from the configuration.
+    pub fn ignored(&self) -> Result<ignore::Ignore, Error> {
+        ignore::Ignore::default().parse("configuration", &self.ignore.join("\n"))
+    }
+
+    /// Replaces the package groups, given as `@<group>`, by the packages they
+    /// contain.
+    pub fn expand(&self, packages: Vec<String>) -> Result<Vec<String>, Error> {
+        let mut expanded = Vec::new();
+        for p in packages {
+            match p.strip_prefix(GROUP_PREFIX) {
+                Some(group) => {
+                    let members = self.groups.get(group).ok_or_else(|| {
+                        Error::Generic(format!("unknown package group {}", group))
+                    })?;
+                    expanded.extend(members.iter().cloned());
+                }
+                None => expanded.push(p),
+            }
+        }
+
+        Ok(expanded)
+    }
+}
+
+/// Tells whether `content` is a `.stowrc` file of GNU stow, which holds
+/// command line flags, one or more per line, instead of TOML.
+fn is_gnu_stowrc(content: &str) -> bool {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .peekable();
+
+    lines.peek().is_some() && lines.all(|line| line.starts_with('-'))
+}
+
+fn resolve(
+    directory: &std::path::Path,
+    home: Option<&std::path::Path>,
+    path: &std::path::Path,
+) -> std::path::PathBuf {
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => path::normalize(&home.join(rest)),
+        _ => path::normalize(&directory.join(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        let file = std::env::temp_dir()
+            .join(format!("stow-config-{}", uuid::Uuid::new_v4()))
+            .join(LOCAL_CONFIG_FILE);
+        std::fs::create_dir(file.parent().unwrap()).expect("cannot create directory");
+        std::fs::write(
+            &file,
+            r#"
+target = "~/../jdoe/.config"
+verbosity = 2
+conflict = "backup"
+ignore = ['.*\.swp']
+
+[groups]
+work = ["zsh", "git"]
+
+[profiles.work-mac]
+packages = ["@work", "vim"]
+tags = ["work"]
+target = "work"
+"#,
+        )
+        .expect("cannot write config");
+
+        let config = Config::load(&file, Some("/home/jdoe".as_ref()));
+        std::fs::remove_dir_all(file.parent().unwrap()).expect("cannot remove directory");
+
+        let config = config.expect("cannot load config");
+        assert_eq!(
+            Config {
+                source: None,
+                target: Some("/home/jdoe/.config".into()),
+                verbosity: Some(2),
+                conflict: Some(plan::ConflictPolicy::Backup),
+                ignore: vec![r".*\.swp".to_string()],
+                tags: vec![],
+                groups: [(
+                    "work".to_string(),
+                    vec!["zsh".to_string(), "git".to_string()]
+                )]
+                .into(),
+                profiles: [(
+                    "work-mac".to_string(),
+                    Profile {
+                        packages: vec!["@work".to_string(), "vim".to_string()],
+                        tags: vec!["work".to_string()],
+                        target: Some(file.parent().unwrap().join("work")),
+                    }
+                )]
+                .into(),
+            },
+            config
+        );
+        assert!(config.ignored().unwrap().is_ignored(".zshrc.swp"));
+    }
+
+    #[test]
+    fn load_invalid_config() {
+        let file = std::env::temp_dir().join(format!("stow-config-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "conflict = \"explode\"\n").expect("cannot write config");
+
+        let err = Config::load(&file, None);
+        std::fs::remove_file(&file).expect("cannot remove config");
+
+        match err.unwrap_err() {
+            Error::ReadFile(err) => assert!(
+                err.reason.contains("unknown conflict policy explode"),
+                "unexpected reason {}",
+                err.reason
+            ),
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn load_gnu_stowrc() {
+        let file = std::env::temp_dir().join(format!("stow-config-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "--target=~\n--ignore=\\.swp --verbose\n")
+            .expect("cannot write config");
+
+        let config = Config::load(&file, None);
+        std::fs::remove_file(&file).expect("cannot remove config");
+
+        assert_eq!(
+            Config::default(),
+            config.expect("GNU stowrc should be skipped")
+        );
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let config =
+            Config::load("/not/a/file".as_ref(), None).expect("missing file should be skipped");
+
+        assert_eq!(Config::default(), config);
+    }
+
+    #[test]
+    fn merge_configs() {
+        let global = Config {
+            source: Some("/home/jdoe/dotfiles".into()),
+            target: Some("/home/jdoe".into()),
+            verbosity: Some(2),
+            tags: vec!["work".to_string()],
+            groups: [("base".to_string(), vec!["zsh".to_string()])].into(),
+            ..Default::default()
+        };
+        let local = Config {
+            target: Some("/opt/jdoe".into()),
+            tags: vec!["linux".to_string()],
+            groups: [("base".to_string(), vec!["bash".to_string()])].into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Config {
+                source: Some("/home/jdoe/dotfiles".into()),
+                target: Some("/opt/jdoe".into()),
+                verbosity: Some(2),
+                conflict: None,
+                ignore: vec![],
+                tags: vec!["work".to_string(), "linux".to_string()],
+                groups: [("base".to_string(), vec!["bash".to_string()])].into(),
+                profiles: Default::default(),
+            },
+            global.merge(local)
+        );
+    }
+
+    #[test]
+    fn expand_groups() {
+        let config = Config {
+            groups: [(
+                "work".to_string(),
+                vec!["zsh".to_string(), "git".to_string()],
+            )]
+            .into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vec!["vim", "zsh", "git"],
+            config
+                .expand(vec!["vim".to_string(), "@work".to_string()])
+                .expect("groups should expand")
+        );
+        assert_eq!(
+            Error::Generic("unknown package group home".to_string()),
+            config.expand(vec!["@home".to_string()]).unwrap_err()
+        );
+    }
+}
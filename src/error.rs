@@ -4,6 +4,12 @@ pub struct ReadFileError {
     pub reason: String,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct WriteFileError {
+    pub file: String,
+    pub reason: String,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct DeleteFileError {
     pub file: String,
@@ -17,6 +23,27 @@ pub struct CreateSymlinkError {
     pub reason: String,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct CreateHardlinkError {
+    pub source: String,
+    pub destination: String,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RenameFileError {
+    pub source: String,
+    pub destination: String,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CopyFileError {
+    pub source: String,
+    pub destination: String,
+    pub reason: String,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct CreateDirectoryError {
     pub directory: String,
@@ -27,11 +54,17 @@ pub struct CreateDirectoryError {
 pub enum Error {
     Generic(String),
     ReadFile(ReadFileError),
+    WriteFile(WriteFileError),
     CreateDirectory(CreateDirectoryError),
     DeleteFile(DeleteFileError),
+    RenameFile(RenameFileError),
+    CopyFile(CopyFileError),
     ParentDirectory(String),
     PackageNotFound(String),
     CreateSymlink(CreateSymlinkError),
+    CreateHardlink(CreateHardlinkError),
+    Conflicts(usize),
+    FailedPackages(usize),
 }
 
 impl std::fmt::Display for Error {
@@ -47,9 +80,26 @@ impl std::fmt::Display for Error {
                     err.directory, err.reason
                 )
             }
+            Self::WriteFile(err) => {
+                write!(f, "file {} cannot be written: {}", err.file, err.reason)
+            }
             Self::DeleteFile(err) => {
                 write!(f, "file {} cannot be removed: {}", err.file, err.reason)
             }
+            Self::RenameFile(err) => {
+                write!(
+                    f,
+                    "file {} cannot be moved to {}: {}",
+                    err.source, err.destination, err.reason
+                )
+            }
+            Self::CopyFile(err) => {
+                write!(
+                    f,
+                    "file {} cannot be copied to {}: {}",
+                    err.source, err.destination, err.reason
+                )
+            }
             Self::ParentDirectory(directory) => {
                 write!(
                     f,
@@ -67,6 +117,23 @@ impl std::fmt::Display for Error {
                     err.source, err.destination, err.reason
                 )
             }
+            Self::CreateHardlink(err) => {
+                write!(
+                    f,
+                    "cannot hardlink from {} to {}: {}",
+                    err.source, err.destination, err.reason
+                )
+            }
+            Self::Conflicts(count) => {
+                write!(
+                    f,
+                    "{} conflict(s) detected, nothing has been changed",
+                    count
+                )
+            }
+            Self::FailedPackages(count) => {
+                write!(f, "{} package(s) failed", count)
+            }
             Self::ReadFile(err) => {
                 write!(
                     f,
@@ -101,6 +168,19 @@ mod tests {
         )
     }
 
+    #[test]
+    fn write_file_error() {
+        let err = super::Error::WriteFile(super::WriteFileError {
+            file: "/folder/file.txt".to_string(),
+            reason: "permission denied".to_string(),
+        });
+
+        assert_eq!(
+            "file /folder/file.txt cannot be written: permission denied",
+            format!("{}", err)
+        )
+    }
+
     #[test]
     fn delete_file_error() {
         let err = super::Error::DeleteFile(super::DeleteFileError {
@@ -114,6 +194,34 @@ mod tests {
         )
     }
 
+    #[test]
+    fn rename_file_error() {
+        let err = super::Error::RenameFile(super::RenameFileError {
+            source: "/folder/file.txt".to_string(),
+            destination: "/backup/file.txt".to_string(),
+            reason: "permission denied".to_string(),
+        });
+
+        assert_eq!(
+            "file /folder/file.txt cannot be moved to /backup/file.txt: permission denied",
+            format!("{}", err)
+        )
+    }
+
+    #[test]
+    fn copy_file_error() {
+        let err = super::Error::CopyFile(super::CopyFileError {
+            source: "/dotfiles/zsh/zshrc".to_string(),
+            destination: "/home/jdoe/.zshrc".to_string(),
+            reason: "permission denied".to_string(),
+        });
+
+        assert_eq!(
+            "file /dotfiles/zsh/zshrc cannot be copied to /home/jdoe/.zshrc: permission denied",
+            format!("{}", err)
+        )
+    }
+
     #[test]
     fn parent_directory_error() {
         let err = super::Error::ParentDirectory("/folder".to_string());
@@ -145,6 +253,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn create_hardlink_error() {
+        let err = super::Error::CreateHardlink(super::CreateHardlinkError {
+            source: "/source/file.txt".to_string(),
+            destination: "/dest/file.txt".to_string(),
+            reason: "different filesystems".to_string(),
+        });
+
+        assert_eq!(
+            "cannot hardlink from /source/file.txt to /dest/file.txt: different filesystems",
+            format!("{}", err)
+        )
+    }
+
     #[test]
     fn read_file_error() {
         let err = super::Error::ReadFile(super::ReadFileError {
@@ -157,4 +279,21 @@ mod tests {
             format!("{}", err)
         )
     }
+
+    #[test]
+    fn conflicts_error() {
+        let err = super::Error::Conflicts(2);
+
+        assert_eq!(
+            "2 conflict(s) detected, nothing has been changed",
+            format!("{}", err)
+        )
+    }
+
+    #[test]
+    fn failed_packages_error() {
+        let err = super::Error::FailedPackages(3);
+
+        assert_eq!("3 package(s) failed", format!("{}", err))
+    }
 }
@@ -0,0 +1,259 @@
+use crate::{plan, status, Error};
+
+/// How the commands report what they do.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    /// Free text lines meant to be read by a human.
+    #[default]
+    Text,
+    /// One JSON event per line, see [`Event`].
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::Generic(format!(
+                "unknown format {} (expected text or json)",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    CreateDirectory,
+    CreateSymlink,
+    CreateHardlink,
+    DeleteFile,
+    RemoveDirectory,
+    Rename,
+    Backup,
+    WriteFile,
+    CopyFile,
+    Conflict,
+    Warning,
+    Error,
+    Package,
+    Status,
+}
+
+/// Something worth reporting about a run, displayed as a single line of
+/// JSON. The fields which don't apply to the kind of event are left out.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<std::path::PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<std::path::PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            package: None,
+            source: None,
+            destination: None,
+            outcome: None,
+            message: None,
+        }
+    }
+
+    pub fn with_package(mut self, package: &str) -> Self {
+        self.package = Some(package.to_string());
+        self
+    }
+
+    pub fn with_source(mut self, source: &std::path::Path) -> Self {
+        self.source = Some(source.to_path_buf());
+        self
+    }
+
+    pub fn with_destination(mut self, destination: &std::path::Path) -> Self {
+        self.destination = Some(destination.to_path_buf());
+        self
+    }
+
+    pub fn with_outcome(mut self, outcome: &str) -> Self {
+        self.outcome = Some(outcome.to_string());
+        self
+    }
+
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    /// Reports an action of a plan, whose outcome is `done`, `planned` when
+    /// the target directory is left untouched, or `failed`.
+    pub fn action(action: &plan::Action, outcome: &str) -> Self {
+        let (kind, source, destination) = match &action.operation {
+            plan::Operation::CreateDirectory(directory) => (Kind::CreateDirectory, None, directory),
+            plan::Operation::CreateSymlink {
+                source,
+                destination,
+            } => (Kind::CreateSymlink, Some(source), destination),
+            plan::Operation::CreateHardlink {
+                source,
+                destination,
+            } => (Kind::CreateHardlink, Some(source), destination),
+            plan::Operation::DeleteFile(file) => (Kind::DeleteFile, None, file),
+            plan::Operation::RemoveDirectory(directory) => (Kind::RemoveDirectory, None, directory),
+            plan::Operation::Rename {
+                source,
+                destination,
+            } => (Kind::Rename, Some(source), destination),
+            plan::Operation::Backup {
+                source,
+                destination,
+            } => (Kind::Backup, Some(source), destination),
+            plan::Operation::WriteFile {
+                source,
+                destination,
+                ..
+            } => (Kind::WriteFile, Some(source), destination),
+            plan::Operation::CopyFile {
+                source,
+                destination,
+                ..
+            } => (Kind::CopyFile, Some(source), destination),
+        };
+
+        let event = Self::new(kind)
+            .with_package(&action.package)
+            .with_destination(destination)
+            .with_outcome(outcome);
+        match source {
+            Some(source) => event.with_source(source),
+            None => event,
+        }
+    }
+
+    /// Reports a conflict, whose outcome is the way it has been resolved.
+    pub fn conflict(conflict: &plan::Conflict) -> Self {
+        Self::new(Kind::Conflict)
+            .with_package(&conflict.package)
+            .with_destination(&conflict.destination)
+            .with_outcome(&conflict.resolution.to_string())
+            .with_message(&conflict.reason)
+    }
+
+    /// Reports the state of the link of a package file, the source being the
+    /// target of the symlink standing in its place, if any.
+    pub fn status(entry: &status::Entry) -> Self {
+        let (outcome, target) = match &entry.state {
+            status::State::Linked => ("linked", None),
+            status::State::Missing => ("missing", None),
+            status::State::Elsewhere(target) => ("elsewhere", Some(target)),
+            status::State::Blocked => ("blocked", None),
+            status::State::Broken(target) => ("broken", Some(target)),
+        };
+
+        let event = Self::new(Kind::Status)
+            .with_package(&entry.package)
+            .with_destination(&entry.destination)
+            .with_outcome(outcome);
+        match target {
+            Some(target) => event.with_source(target),
+            None => event,
+        }
+    }
+
+    pub fn error(err: &Error) -> Self {
+        Self::new(Kind::Error)
+            .with_outcome("failed")
+            .with_message(&err.to_string())
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_format() {
+        assert_eq!(Ok(Format::Text), "text".parse::<Format>());
+        assert_eq!(Ok(Format::Json), "json".parse::<Format>());
+        assert_eq!(
+            Err(Error::Generic(
+                "unknown format yaml (expected text or json)".to_string()
+            )),
+            "yaml".parse::<Format>()
+        );
+    }
+
+    #[test]
+    fn display_action() {
+        let action = plan::Action {
+            package: "zsh".to_string(),
+            operation: plan::Operation::CreateSymlink {
+                source: "../dotfiles/zsh/.zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        };
+
+        assert_eq!(
+            r#"{"type":"create-symlink","package":"zsh","source":"../dotfiles/zsh/.zshrc","destination":"/home/jdoe/.zshrc","outcome":"done"}"#,
+            Event::action(&action, "done").to_string()
+        );
+    }
+
+    #[test]
+    fn display_conflict() {
+        let conflict = plan::Conflict {
+            package: "zsh".to_string(),
+            destination: "/home/jdoe/.zshrc".into(),
+            reason: "existing file".to_string(),
+            resolution: plan::ConflictPolicy::Backup,
+        };
+
+        assert_eq!(
+            r#"{"type":"conflict","package":"zsh","destination":"/home/jdoe/.zshrc","outcome":"backed up","message":"existing file"}"#,
+            Event::conflict(&conflict).to_string()
+        );
+    }
+
+    #[test]
+    fn display_status() {
+        let entry = status::Entry {
+            package: "zsh".to_string(),
+            destination: "/home/jdoe/.zshrc".into(),
+            state: status::State::Broken("/dotfiles/zsh/.zshrc".into()),
+        };
+
+        assert_eq!(
+            r#"{"type":"status","package":"zsh","source":"/dotfiles/zsh/.zshrc","destination":"/home/jdoe/.zshrc","outcome":"broken"}"#,
+            Event::status(&entry).to_string()
+        );
+    }
+
+    #[test]
+    fn display_error() {
+        assert_eq!(
+            r#"{"type":"error","outcome":"failed","message":"package vim does not exist"}"#,
+            Event::error(&Error::PackageNotFound("vim".to_string())).to_string()
+        );
+    }
+}
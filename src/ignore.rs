@@ -0,0 +1,184 @@
+use crate::{Error, ReadFileError};
+
+/// Name of the file listing the patterns to ignore in a package.
+pub const LOCAL_IGNORE_FILE: &str = ".stow-local-ignore";
+
+/// Name of the file, in the home directory, listing the patterns to ignore in
+/// every package.
+pub const GLOBAL_IGNORE_FILE: &str = ".stow-global-ignore";
+
+/// Patterns always ignored: version control files and stow own files.
+const DEFAULT_PATTERNS: &[&str] = &[
+    "RCS",
+    "CVS",
+    r"\.git",
+    r"\.gitignore",
+    r"\.gitmodules",
+    r"\.hg",
+    r"\.svn",
+    r"\.bzr",
+    "_darcs",
+    r"\.stow-.*",
+];
+
+/// Set of patterns filtering out package files.
+///
+/// Each pattern is a regular expression which must match a whole path. A
+/// pattern containing a `/` is matched against the path relative to the
+/// package root prefixed with a `/` (e.g. `/docs/.*`), any other pattern is
+/// matched against the file name only (e.g. `.*\.swp`).
+#[derive(Debug, Default, Clone)]
+pub struct Ignore {
+    patterns: Vec<regex::Regex>,
+}
+
+impl Ignore {
+    /// Creates a set containing the patterns ignored in every package.
+    pub fn builtin() -> Self {
+        let patterns = DEFAULT_PATTERNS
+            .iter()
+            .map(|p| compile(p).expect("default ignore patterns should be valid"))
+            .collect();
+
+        Self { patterns }
+    }
+
+    /// Adds the patterns listed in `content`, one per line. Empty lines and
+    /// lines starting with a `#` are skipped.
+    pub fn parse(mut self, file: &str, content: &str) -> Result<Self, Error> {
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let pattern = compile(line).map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: file.to_string(),
+                    reason: format!("invalid pattern {}: {}", line, e),
+                })
+            })?;
+            self.patterns.push(pattern);
+        }
+
+        Ok(self)
+    }
+
+    /// Adds the patterns listed in `file`, if it exists.
+    pub fn load(self, file: &std::path::Path) -> Result<Self, Error> {
+        match std::fs::read_to_string(file) {
+            Ok(content) => self.parse(&file.display().to_string(), &content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(self),
+            Err(e) => Err(Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason: e.to_string(),
+            })),
+        }
+    }
+
+    /// Adds the patterns of another set.
+    pub fn extend(mut self, other: &Ignore) -> Self {
+        self.patterns.extend(other.patterns.iter().cloned());
+        self
+    }
+
+    /// Tells whether `file`, relative to the package root, is ignored.
+    pub fn is_ignored(&self, file: &str) -> bool {
+        if file.is_empty() {
+            return false;
+        }
+
+        let full_path = format!("/{}", file);
+        let name = std::path::Path::new(file)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(file);
+
+        self.patterns.iter().any(|p| {
+            if p.as_str().contains('/') {
+                p.is_match(&full_path)
+            } else {
+                p.is_match(name)
+            }
+        })
+    }
+}
+
+fn compile(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::Regex::new(&format!("^(?:{})$", pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_patterns() {
+        let ignore = Ignore::builtin();
+
+        let testcases = vec![
+            (".git", true),
+            (".git/config", false),
+            ("subdirectory/.svn", true),
+            (".stow-local-ignore", true),
+            (".stow-state", true),
+            (".gitconfig", false),
+            ("file-1", false),
+            ("", false),
+        ];
+
+        for (file, expected) in testcases {
+            assert_eq!(expected, ignore.is_ignored(file), "file {}", file)
+        }
+    }
+
+    #[test]
+    fn parse_patterns() {
+        let ignore = Ignore::default()
+            .parse(
+                "ignore",
+                "# editor files\n.*\\.swp\n\n  README.*  \n/docs/.*\n",
+            )
+            .expect("patterns should be valid");
+
+        let testcases = vec![
+            (".vimrc.swp", true),
+            ("subdirectory/.zshrc.swp", true),
+            ("README.md", true),
+            ("subdirectory/README", true),
+            ("docs/usage", true),
+            ("subdirectory/docs/usage", false),
+            ("# editor files", false),
+            (".vimrc", false),
+        ];
+
+        for (file, expected) in testcases {
+            assert_eq!(expected, ignore.is_ignored(file), "file {}", file)
+        }
+    }
+
+    #[test]
+    fn parse_invalid_pattern() {
+        let err = Ignore::default().parse("ignore", "file-(").unwrap_err();
+
+        match err {
+            Error::ReadFile(err) => {
+                assert_eq!("ignore", err.file);
+                assert!(
+                    err.reason.starts_with("invalid pattern file-(:"),
+                    "unexpected reason {}",
+                    err.reason
+                );
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let ignore = Ignore::default()
+            .load("/not/a/file".as_ref())
+            .expect("missing file should be skipped");
+
+        assert!(!ignore.is_ignored("file-1"));
+    }
+}
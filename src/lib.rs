@@ -1,8 +1,16 @@
 pub mod command;
+pub mod config;
 mod error;
+pub mod event;
+pub mod ignore;
 pub mod linker;
 pub mod package;
 pub mod path;
+pub mod plan;
+pub mod state;
+pub mod status;
+pub mod template;
+pub mod variant;
 pub mod writer;
 
 pub use error::*;
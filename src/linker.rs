@@ -1,7 +1,11 @@
 use crate::{
-    path, CreateDirectoryError, CreateSymlinkError, DeleteFileError, Error, ReadFileError,
+    path, CopyFileError, CreateDirectoryError, CreateHardlinkError, CreateSymlinkError,
+    DeleteFileError, Error, ReadFileError, RenameFileError, WriteFileError,
 };
 
+/// Reason given when a hardlink would cross filesystems.
+pub const CROSS_DEVICE_REASON: &str = "source and destination are on different filesystems";
+
 pub trait Linker {
     fn canonicalize(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error>;
 
@@ -11,6 +15,14 @@ pub trait Linker {
         destination: &path::Destination,
     ) -> Result<(), Error>;
 
+    /// Creates `destination` as a hardlink to the regular file `source`,
+    /// which must be on the same filesystem.
+    fn create_hardlink(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error>;
+
     fn create_directory(&mut self, directory: &std::path::Path) -> Result<(), Error>;
 
     fn directory_exists(&mut self, directory: &std::path::Path) -> Result<bool, Error>;
@@ -19,25 +31,112 @@ pub trait Linker {
 
     fn is_symlink(&mut self, file: &std::path::Path) -> bool;
 
+    /// Tells whether `file` and `other` are the same file, i.e. have the same
+    /// device and inode. Missing files are never the same.
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error>;
+
+    /// Tells whether `file` and `other`, which must exist, are on the same
+    /// filesystem.
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error>;
+
     fn list_symlinks(
         &mut self,
         directory: &std::path::Path,
     ) -> Result<Vec<std::path::PathBuf>, Error>;
 
+    /// Lists the directories placed right below `directory`, symlinks to
+    /// directories excluded.
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error>;
+
+    /// Lists all the entries placed right below `directory`.
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error>;
+
     fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error>;
 
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error>;
+
+    /// Creates the regular file `file` with `content`. Nothing must exist at
+    /// its location.
+    fn write_file(&mut self, file: &std::path::Path, content: &[u8]) -> Result<(), Error>;
+
+    /// Copies the regular file `source` to `destination`, along with its
+    /// permissions. Nothing must exist at the destination.
+    fn copy_file(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error>;
+
     fn delete_file(&mut self, file: &std::path::Path) -> Result<(), Error>;
+
+    /// Removes `directory`, which must be empty.
+    fn remove_directory(&mut self, directory: &std::path::Path) -> Result<(), Error>;
+
+    fn rename(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error>;
 }
 
 #[derive(Default)]
 pub struct Noop {
+    /// Directory relative paths are resolved from.
+    pub current_directory: std::path::PathBuf,
     pub directories: Vec<std::path::PathBuf>,
+    /// Symlinks, stored as (link, target) pairs.
     pub files: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    pub regular_files: Vec<std::path::PathBuf>,
+    /// Content of the regular files, which are empty when not listed.
+    pub contents: Vec<(std::path::PathBuf, Vec<u8>)>,
+    /// Hardlinks, stored as (link, file) pairs. Both are regular files too.
+    pub hardlinks: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    /// Mount points of filesystems other than the root one.
+    pub mounts: Vec<std::path::PathBuf>,
+    /// Directories whose content cannot be listed.
+    pub unreadable: Vec<std::path::PathBuf>,
+}
+
+impl Noop {
+    /// Mount point of the filesystem containing `file`.
+    fn filesystem(&self, file: &std::path::Path) -> std::path::PathBuf {
+        self.mounts
+            .iter()
+            .filter(|m| file.starts_with(m))
+            .max_by_key(|m| m.components().count())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn check_readable(&self, directory: &std::path::Path) -> Result<(), Error> {
+        if self.unreadable.iter().any(|d| d == directory) {
+            return Err(Error::ReadFile(ReadFileError {
+                file: directory.display().to_string(),
+                reason: "permission denied".to_string(),
+            }));
+        }
+
+        Ok(())
+    }
 }
 
 impl Linker for Noop {
     fn canonicalize(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
-        Ok(file.to_path_buf())
+        Ok(path::normalize(&self.current_directory.join(file)))
     }
 
     fn create_symlink(
@@ -61,6 +160,33 @@ impl Linker for Noop {
         Ok(())
     }
 
+    fn create_hardlink(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        let hardlink_error = |reason: &str| {
+            Err(Error::CreateHardlink(CreateHardlinkError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: reason.to_string(),
+            }))
+        };
+
+        if self.file_exists(destination)? || self.directory_exists(destination)? {
+            return hardlink_error("file already exists");
+        }
+        if self.filesystem(source) != self.filesystem(destination) {
+            return hardlink_error(CROSS_DEVICE_REASON);
+        }
+
+        self.regular_files.push(destination.to_path_buf());
+        self.hardlinks
+            .push((destination.to_path_buf(), source.to_path_buf()));
+
+        Ok(())
+    }
+
     fn create_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
         if self.directory_exists(directory)? {
             return Err(Error::CreateDirectory(CreateDirectoryError {
@@ -79,29 +205,82 @@ impl Linker for Noop {
     }
 
     fn file_exists(&mut self, file: &std::path::Path) -> Result<bool, Error> {
-        Ok(self.files.iter().any(|(f, _)| f.as_path() == file))
+        Ok(self.is_symlink(file) || self.regular_files.iter().any(|f| f.as_path() == file))
     }
 
     fn is_symlink(&mut self, file: &std::path::Path) -> bool {
         self.files.iter().any(|(f, _)| f.as_path() == file)
     }
 
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        Ok(file == other && self.file_exists(file)?
+            || self
+                .hardlinks
+                .iter()
+                .any(|(l, f)| (l == file && f == other) || (l == other && f == file)))
+    }
+
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        Ok(self.filesystem(file) == self.filesystem(other))
+    }
+
     fn list_symlinks(
         &mut self,
         directory: &std::path::Path,
     ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.check_readable(directory)?;
         let symlinks = self
             .files
             .iter()
-            .filter_map(|(file, _)| file.parent().map(|p| p == directory).and(Some(file)))
-            .map(|p| p.to_path_buf())
+            .filter(|(file, _)| file.parent() == Some(directory))
+            .map(|(file, _)| file.to_path_buf())
             .collect::<Vec<std::path::PathBuf>>();
 
         Ok(symlinks)
     }
 
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.check_readable(directory)?;
+        let directories = self
+            .directories
+            .iter()
+            .filter(|d| d.parent() == Some(directory))
+            .cloned()
+            .collect::<Vec<std::path::PathBuf>>();
+
+        Ok(directories)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.check_readable(directory)?;
+        let entries = self
+            .directories
+            .iter()
+            .chain(self.files.iter().map(|(file, _)| file))
+            .chain(self.regular_files.iter())
+            .filter(|f| f.parent() == Some(directory))
+            .cloned()
+            .collect::<Vec<std::path::PathBuf>>();
+
+        Ok(entries)
+    }
+
     fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
-        if !self.file_exists(file)? {
+        if !self.is_symlink(file) {
             return Err(Error::ReadFile(ReadFileError {
                 file: file.display().to_string(),
                 reason: "file does not exist".to_string(),
@@ -118,6 +297,62 @@ impl Linker for Noop {
         Ok(target.to_path_buf())
     }
 
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error> {
+        if !self.regular_files.iter().any(|f| f.as_path() == file) {
+            return Err(Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason: "file does not exist".to_string(),
+            }));
+        }
+
+        let content = self
+            .contents
+            .iter()
+            .find(|(f, _)| f.as_path() == file)
+            .map(|(_, content)| content.clone())
+            .unwrap_or_default();
+
+        Ok(content)
+    }
+
+    fn write_file(&mut self, file: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+        if self.file_exists(file)? || self.directory_exists(file)? {
+            return Err(Error::WriteFile(WriteFileError {
+                file: file.display().to_string(),
+                reason: "file already exists".to_string(),
+            }));
+        }
+
+        self.regular_files.push(file.to_path_buf());
+        self.contents.push((file.to_path_buf(), content.to_vec()));
+        Ok(())
+    }
+
+    fn copy_file(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        if self.file_exists(destination)? || self.directory_exists(destination)? {
+            return Err(Error::CopyFile(CopyFileError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: "file already exists".to_string(),
+            }));
+        }
+
+        let content = self
+            .contents
+            .iter()
+            .find(|(f, _)| f.as_path() == source)
+            .map(|(_, content)| content.clone())
+            .unwrap_or_default();
+
+        self.regular_files.push(destination.to_path_buf());
+        self.contents.push((destination.to_path_buf(), content));
+        Ok(())
+    }
+
     fn delete_file(&mut self, file: &std::path::Path) -> Result<(), Error> {
         if !self.file_exists(file)? {
             return Err(Error::DeleteFile(DeleteFileError {
@@ -126,14 +361,69 @@ impl Linker for Noop {
             }));
         }
 
-        let index = self
-            .files
-            .iter()
-            .enumerate()
-            .find(|(_index, (f, _))| f.as_path() == file)
-            .map(|(index, _file)| index)
-            .unwrap();
-        self.files.remove(index);
+        self.files.retain(|(f, _)| f.as_path() != file);
+        self.regular_files.retain(|f| f.as_path() != file);
+        self.hardlinks
+            .retain(|(l, f)| l.as_path() != file && f.as_path() != file);
+        self.contents.retain(|(f, _)| f.as_path() != file);
+        Ok(())
+    }
+
+    fn remove_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
+        let reason = if !self.directory_exists(directory)? {
+            Some("directory does not exist")
+        } else if !self.read_directory(directory)?.is_empty() {
+            Some("directory is not empty")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            return Err(Error::DeleteFile(DeleteFileError {
+                file: directory.display().to_string(),
+                reason: reason.to_string(),
+            }));
+        }
+
+        self.directories.retain(|d| d.as_path() != directory);
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        if !self.file_exists(source)? {
+            return Err(Error::RenameFile(RenameFileError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: "file does not exist".to_string(),
+            }));
+        }
+
+        self.files.retain(|(f, _)| f.as_path() != destination);
+        self.regular_files.retain(|f| f.as_path() != destination);
+
+        for (file, _) in self.files.iter_mut().filter(|(f, _)| f.as_path() == source) {
+            *file = destination.to_path_buf();
+        }
+        for file in self
+            .regular_files
+            .iter_mut()
+            .filter(|f| f.as_path() == source)
+        {
+            *file = destination.to_path_buf();
+        }
+        self.contents.retain(|(f, _)| f.as_path() != destination);
+        for (file, _) in self
+            .contents
+            .iter_mut()
+            .filter(|(f, _)| f.as_path() == source)
+        {
+            *file = destination.to_path_buf();
+        }
+
         Ok(())
     }
 }
@@ -164,6 +454,22 @@ impl<W: std::io::Write, L: Linker> Linker for Verbose<W, L> {
         self.linker.create_symlink(source, destination)
     }
 
+    fn create_hardlink(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        writeln!(
+            self.logger,
+            "ln {} {}",
+            source.display(),
+            destination.display()
+        )
+        .map_err(|e| Error::Generic(format!("cannot write ln log: {}", e)))?;
+
+        self.linker.create_hardlink(source, destination)
+    }
+
     fn create_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
         writeln!(self.logger, "mkdir -p {}", directory.display())
             .map_err(|e| Error::Generic(format!("cannot write mkdir log: {}", e)))?;
@@ -183,6 +489,22 @@ impl<W: std::io::Write, L: Linker> Linker for Verbose<W, L> {
         self.linker.is_symlink(file)
     }
 
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_file(file, other)
+    }
+
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_filesystem(file, other)
+    }
+
     fn list_symlinks(
         &mut self,
         directory: &std::path::Path,
@@ -190,6 +512,20 @@ impl<W: std::io::Write, L: Linker> Linker for Verbose<W, L> {
         self.linker.list_symlinks(directory)
     }
 
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.list_directories(directory)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.read_directory(directory)
+    }
+
     fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
         writeln!(self.logger, "readlink {}", file.display())
             .map_err(|e| Error::Generic(format!("cannot write readlink log: {}", e)))?;
@@ -197,12 +533,384 @@ impl<W: std::io::Write, L: Linker> Linker for Verbose<W, L> {
         self.linker.read_link(file)
     }
 
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error> {
+        self.linker.read_file(file)
+    }
+
+    fn write_file(&mut self, file: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+        writeln!(self.logger, "write {}", file.display())
+            .map_err(|e| Error::Generic(format!("cannot write write log: {}", e)))?;
+
+        self.linker.write_file(file, content)
+    }
+
+    fn copy_file(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        writeln!(
+            self.logger,
+            "cp {} {}",
+            source.display(),
+            destination.display()
+        )
+        .map_err(|e| Error::Generic(format!("cannot write cp log: {}", e)))?;
+
+        self.linker.copy_file(source, destination)
+    }
+
     fn delete_file(&mut self, file: &std::path::Path) -> Result<(), Error> {
         writeln!(self.logger, "rm {}", file.display())
             .map_err(|e| Error::Generic(format!("cannot write rm log: {}", e)))?;
 
         self.linker.delete_file(file)
     }
+
+    fn remove_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
+        writeln!(self.logger, "rmdir {}", directory.display())
+            .map_err(|e| Error::Generic(format!("cannot write rmdir log: {}", e)))?;
+
+        self.linker.remove_directory(directory)
+    }
+
+    fn rename(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        writeln!(
+            self.logger,
+            "mv {} {}",
+            source.display(),
+            destination.display()
+        )
+        .map_err(|e| Error::Generic(format!("cannot write mv log: {}", e)))?;
+
+        self.linker.rename(source, destination)
+    }
+}
+
+/// Linker reading the actual state of the files through another linker but
+/// never modifying them.
+pub struct DryRun<L: Linker> {
+    linker: L,
+}
+
+impl<L: Linker> DryRun<L> {
+    pub fn new(linker: L) -> Self {
+        DryRun { linker }
+    }
+}
+
+impl<L: Linker> Linker for DryRun<L> {
+    fn canonicalize(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
+        self.linker.canonicalize(file)
+    }
+
+    fn create_symlink(
+        &mut self,
+        _source: &path::Source,
+        _destination: &path::Destination,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn create_hardlink(
+        &mut self,
+        _source: &std::path::Path,
+        _destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn create_directory(&mut self, _directory: &std::path::Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn directory_exists(&mut self, directory: &std::path::Path) -> Result<bool, Error> {
+        self.linker.directory_exists(directory)
+    }
+
+    fn file_exists(&mut self, file: &std::path::Path) -> Result<bool, Error> {
+        self.linker.file_exists(file)
+    }
+
+    fn is_symlink(&mut self, file: &std::path::Path) -> bool {
+        self.linker.is_symlink(file)
+    }
+
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_file(file, other)
+    }
+
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_filesystem(file, other)
+    }
+
+    fn list_symlinks(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.list_symlinks(directory)
+    }
+
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.list_directories(directory)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.read_directory(directory)
+    }
+
+    fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
+        self.linker.read_link(file)
+    }
+
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error> {
+        self.linker.read_file(file)
+    }
+
+    fn write_file(&mut self, _file: &std::path::Path, _content: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn copy_file(
+        &mut self,
+        _source: &std::path::Path,
+        _destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn delete_file(&mut self, _file: &std::path::Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn remove_directory(&mut self, _directory: &std::path::Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        _source: &std::path::Path,
+        _destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Linker printing the operations modifying the files as a POSIX shell
+/// script, stopping at the first failing command, before running them with
+/// another linker. Reading the files prints nothing.
+pub struct Script<W: std::io::Write, L: Linker> {
+    writer: W,
+    linker: L,
+    started: bool,
+}
+
+impl<W: std::io::Write, L: Linker> Script<W, L> {
+    pub fn new(writer: W, linker: L) -> Self {
+        Script {
+            writer,
+            linker,
+            started: false,
+        }
+    }
+
+    fn print(
+        &mut self,
+        command: &str,
+        arguments: &[&[u8]],
+        output: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let mut line = Vec::new();
+        if !self.started {
+            line.extend_from_slice(b"#!/bin/sh\nset -e\n");
+        }
+        line.extend_from_slice(command.as_bytes());
+        for argument in arguments {
+            line.push(b' ');
+            line.extend(quote(argument));
+        }
+        if let Some(output) = output {
+            line.extend_from_slice(b" > ");
+            line.extend(quote(output));
+        }
+        line.push(b'\n');
+
+        self.writer.write_all(&line).map_err(|e| {
+            Error::Generic(format!("cannot write {} to the script: {}", command, e))
+        })?;
+        self.started = true;
+
+        Ok(())
+    }
+}
+
+/// Quotes `argument` for a POSIX shell, between single quotes.
+fn quote(argument: &[u8]) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &byte in argument {
+        match byte {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            _ => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+fn bytes(file: &std::path::Path) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt;
+
+    file.as_os_str().as_bytes()
+}
+
+impl<W: std::io::Write, L: Linker> Linker for Script<W, L> {
+    fn canonicalize(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
+        self.linker.canonicalize(file)
+    }
+
+    fn create_symlink(
+        &mut self,
+        source: &path::Source,
+        destination: &path::Destination,
+    ) -> Result<(), Error> {
+        self.print(
+            "ln -s --",
+            &[bytes(source.as_ref()), bytes(destination.as_ref())],
+            None,
+        )?;
+
+        self.linker.create_symlink(source, destination)
+    }
+
+    fn create_hardlink(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        self.print("ln --", &[bytes(source), bytes(destination)], None)?;
+
+        self.linker.create_hardlink(source, destination)
+    }
+
+    fn create_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
+        self.print("mkdir -p --", &[bytes(directory)], None)?;
+
+        self.linker.create_directory(directory)
+    }
+
+    fn directory_exists(&mut self, directory: &std::path::Path) -> Result<bool, Error> {
+        self.linker.directory_exists(directory)
+    }
+
+    fn file_exists(&mut self, file: &std::path::Path) -> Result<bool, Error> {
+        self.linker.file_exists(file)
+    }
+
+    fn is_symlink(&mut self, file: &std::path::Path) -> bool {
+        self.linker.is_symlink(file)
+    }
+
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_file(file, other)
+    }
+
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        self.linker.is_same_filesystem(file, other)
+    }
+
+    fn list_symlinks(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.list_symlinks(directory)
+    }
+
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.list_directories(directory)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        self.linker.read_directory(directory)
+    }
+
+    fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
+        self.linker.read_link(file)
+    }
+
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error> {
+        self.linker.read_file(file)
+    }
+
+    fn write_file(&mut self, file: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+        self.print("printf '%s'", &[content], Some(bytes(file)))?;
+
+        self.linker.write_file(file, content)
+    }
+
+    fn copy_file(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        self.print("cp --", &[bytes(source), bytes(destination)], None)?;
+
+        self.linker.copy_file(source, destination)
+    }
+
+    fn delete_file(&mut self, file: &std::path::Path) -> Result<(), Error> {
+        // without -f, rm asks before removing a read-only file
+        self.print("rm -f --", &[bytes(file)], None)?;
+
+        self.linker.delete_file(file)
+    }
+
+    fn remove_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
+        self.print("rmdir --", &[bytes(directory)], None)?;
+
+        self.linker.remove_directory(directory)
+    }
+
+    fn rename(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        self.print("mv --", &[bytes(source), bytes(destination)], None)?;
+
+        self.linker.rename(source, destination)
+    }
 }
 
 pub struct Filesystem;
@@ -227,6 +935,24 @@ impl Linker for Filesystem {
         })
     }
 
+    fn create_hardlink(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        std::fs::hard_link(source, destination).map_err(|e| {
+            Error::CreateHardlink(CreateHardlinkError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: if e.kind() == std::io::ErrorKind::CrossesDevices {
+                    CROSS_DEVICE_REASON.to_string()
+                } else {
+                    e.to_string()
+                },
+            })
+        })
+    }
+
     fn create_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
         std::fs::create_dir_all(directory).map_err(|e| {
             Error::CreateDirectory(CreateDirectoryError {
@@ -262,18 +988,99 @@ impl Linker for Filesystem {
         file.is_symlink()
     }
 
+    fn is_same_file(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = |f: &std::path::Path| match std::fs::symlink_metadata(f) {
+            Ok(m) => Ok(Some((m.dev(), m.ino()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::ReadFile(ReadFileError {
+                file: f.display().to_string(),
+                reason: e.to_string(),
+            })),
+        };
+
+        match (metadata(file)?, metadata(other)?) {
+            (Some(file), Some(other)) => Ok(file == other),
+            _ => Ok(false),
+        }
+    }
+
+    fn is_same_filesystem(
+        &mut self,
+        file: &std::path::Path,
+        other: &std::path::Path,
+    ) -> Result<bool, Error> {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = |f: &std::path::Path| {
+            std::fs::metadata(f).map(|m| m.dev()).map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: f.display().to_string(),
+                    reason: e.to_string(),
+                })
+            })
+        };
+
+        Ok(device(file)? == device(other)?)
+    }
+
     fn list_symlinks(
         &mut self,
         directory: &std::path::Path,
     ) -> Result<Vec<std::path::PathBuf>, Error> {
         let symlinks = std::fs::read_dir(directory)
-            .map_err(|e| Error::Generic(e.to_string()))?
+            .map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: directory.display().to_string(),
+                    reason: e.to_string(),
+                })
+            })?
             .filter_map(|dir| dir.ok().map(|dir| dir.path()))
             .filter(|p| p.is_symlink())
             .collect::<Vec<std::path::PathBuf>>();
         Ok(symlinks)
     }
 
+    fn list_directories(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        let mut directories = std::fs::read_dir(directory)
+            .map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: directory.display().to_string(),
+                    reason: e.to_string(),
+                })
+            })?
+            .filter_map(|dir| dir.ok().map(|dir| dir.path()))
+            .filter(|p| p.is_dir() && !p.is_symlink())
+            .collect::<Vec<std::path::PathBuf>>();
+        directories.sort();
+        Ok(directories)
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: &std::path::Path,
+    ) -> Result<Vec<std::path::PathBuf>, Error> {
+        let mut entries = std::fs::read_dir(directory)
+            .map_err(|e| {
+                Error::ReadFile(ReadFileError {
+                    file: directory.display().to_string(),
+                    reason: e.to_string(),
+                })
+            })?
+            .filter_map(|dir| dir.ok().map(|dir| dir.path()))
+            .collect::<Vec<std::path::PathBuf>>();
+        entries.sort();
+        Ok(entries)
+    }
+
     fn read_link(&mut self, file: &std::path::Path) -> Result<std::path::PathBuf, Error> {
         std::fs::read_link(file).map_err(|e| {
             Error::ReadFile(ReadFileError {
@@ -283,6 +1090,55 @@ impl Linker for Filesystem {
         })
     }
 
+    fn read_file(&mut self, file: &std::path::Path) -> Result<Vec<u8>, Error> {
+        std::fs::read(file).map_err(|e| {
+            Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason: e.to_string(),
+            })
+        })
+    }
+
+    fn write_file(&mut self, file: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+        let write_error = |e: std::io::Error| {
+            Error::WriteFile(WriteFileError {
+                file: file.display().to_string(),
+                reason: e.to_string(),
+            })
+        };
+
+        let mut f = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(file)
+            .map_err(write_error)?;
+        std::io::Write::write_all(&mut f, content).map_err(write_error)
+    }
+
+    fn copy_file(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        let copy_error = |e: std::io::Error| {
+            Error::CopyFile(CopyFileError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: e.to_string(),
+            })
+        };
+
+        let mut input = std::fs::File::open(source).map_err(copy_error)?;
+        let permissions = input.metadata().map_err(copy_error)?.permissions();
+        let mut output = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(destination)
+            .map_err(copy_error)?;
+        std::io::copy(&mut input, &mut output).map_err(copy_error)?;
+        output.set_permissions(permissions).map_err(copy_error)
+    }
+
     fn delete_file(&mut self, file: &std::path::Path) -> Result<(), Error> {
         std::fs::remove_file(file).map_err(|e| {
             Error::DeleteFile(DeleteFileError {
@@ -291,10 +1147,33 @@ impl Linker for Filesystem {
             })
         })
     }
+
+    fn remove_directory(&mut self, directory: &std::path::Path) -> Result<(), Error> {
+        std::fs::remove_dir(directory).map_err(|e| {
+            Error::DeleteFile(DeleteFileError {
+                file: directory.display().to_string(),
+                reason: e.to_string(),
+            })
+        })
+    }
+
+    fn rename(
+        &mut self,
+        source: &std::path::Path,
+        destination: &std::path::Path,
+    ) -> Result<(), Error> {
+        std::fs::rename(source, destination).map_err(|e| {
+            Error::RenameFile(RenameFileError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: e.to_string(),
+            })
+        })
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::io::Write;
 
     use super::*;
@@ -314,6 +1193,56 @@ mod tests {
         assert_eq!("ln -s /from/path /to/path\n", content)
     }
 
+    #[test]
+    fn verbose_noop_hardlink() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut dryrunner = Verbose::new(
+            &mut output,
+            Noop {
+                regular_files: vec!["/from/path".into()],
+                ..Default::default()
+            },
+        );
+        dryrunner
+            .create_hardlink("/from/path".as_ref(), "/to/path".as_ref())
+            .expect("cannot link path");
+
+        assert!(dryrunner
+            .is_same_file("/to/path".as_ref(), "/from/path".as_ref())
+            .expect("cannot compare files"));
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!("ln /from/path /to/path\n", content)
+    }
+
+    #[test]
+    fn noop_hardlink_across_filesystems() {
+        let mut noop = Noop {
+            regular_files: vec!["/home/jdoe/dotfiles/zshrc".into()],
+            mounts: vec!["/home".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Error::CreateHardlink(CreateHardlinkError {
+                source: "/home/jdoe/dotfiles/zshrc".to_string(),
+                destination: "/root/.zshrc".to_string(),
+                reason: CROSS_DEVICE_REASON.to_string(),
+            }),
+            noop.create_hardlink(
+                "/home/jdoe/dotfiles/zshrc".as_ref(),
+                "/root/.zshrc".as_ref()
+            )
+            .unwrap_err()
+        );
+        noop.create_hardlink(
+            "/home/jdoe/dotfiles/zshrc".as_ref(),
+            "/home/jdoe/.zshrc".as_ref(),
+        )
+        .expect("cannot link path");
+    }
+
     #[test]
     fn verbose_create_directory() {
         let mut output = std::io::BufWriter::new(Vec::new());
@@ -396,6 +1325,316 @@ mod tests {
         assert_eq!("ln -s /from/path a/nice/path\nrm a/nice/path\n", content)
     }
 
+    #[test]
+    fn verbose_write_file() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut dryrunner = Verbose::new(&mut output, Noop::default());
+
+        dryrunner
+            .write_file("a/nice/path".as_ref(), b"content")
+            .expect("cannot write file");
+
+        assert_eq!(
+            b"content".to_vec(),
+            dryrunner
+                .read_file("a/nice/path".as_ref())
+                .expect("cannot read file")
+        );
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!("write a/nice/path\n", content)
+    }
+
+    #[test]
+    fn verbose_copy_file() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut dryrunner = Verbose::new(
+            &mut output,
+            Noop {
+                regular_files: vec!["a/nice/path".into()],
+                contents: vec![("a/nice/path".into(), b"content".to_vec())],
+                ..Default::default()
+            },
+        );
+
+        dryrunner
+            .copy_file("a/nice/path".as_ref(), "a/copied/path".as_ref())
+            .expect("cannot copy file");
+
+        assert_eq!(
+            b"content".to_vec(),
+            dryrunner
+                .read_file("a/copied/path".as_ref())
+                .expect("cannot read file")
+        );
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!("cp a/nice/path a/copied/path\n", content)
+    }
+
+    #[test]
+    fn verbose_remove_directory() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut dryrunner = Verbose::new(&mut output, Noop::default());
+
+        dryrunner
+            .create_directory("a/nice/path".as_ref())
+            .expect("cannot create directory");
+        dryrunner
+            .remove_directory("a/nice/path".as_ref())
+            .expect("cannot remove directory");
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!("mkdir -p a/nice/path\nrmdir a/nice/path\n", content)
+    }
+
+    #[test]
+    fn verbose_rename() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut dryrunner = Verbose::new(
+            &mut output,
+            Noop {
+                regular_files: vec!["a/nice/path".into()],
+                ..Default::default()
+            },
+        );
+
+        dryrunner
+            .rename("a/nice/path".as_ref(), "a/backup/path".as_ref())
+            .expect("cannot rename path");
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!("mv a/nice/path a/backup/path\n", content)
+    }
+
+    #[test]
+    fn noop_remove_directory() {
+        let mut noop = Noop {
+            directories: vec!["/home/jdoe".into(), "/home/jdoe/.config".into()],
+            regular_files: vec!["/home/jdoe/.config/file".into()],
+            ..Default::default()
+        };
+        let directory = std::path::Path::new("/home/jdoe/.config");
+
+        assert_eq!(
+            Error::DeleteFile(DeleteFileError {
+                file: "/home/jdoe/.config".to_string(),
+                reason: "directory is not empty".to_string(),
+            }),
+            noop.remove_directory(directory).unwrap_err()
+        );
+
+        noop.delete_file("/home/jdoe/.config/file".as_ref())
+            .expect("cannot delete file");
+        noop.remove_directory(directory)
+            .expect("cannot remove directory");
+
+        assert_eq!(
+            vec![std::path::PathBuf::from("/home/jdoe")],
+            noop.directories
+        );
+        assert_eq!(
+            Error::DeleteFile(DeleteFileError {
+                file: "/home/jdoe/.config".to_string(),
+                reason: "directory does not exist".to_string(),
+            }),
+            noop.remove_directory(directory).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn noop_rename() {
+        let mut noop = Noop {
+            files: vec![("/home/jdoe/link".into(), "/etc/file".into())],
+            regular_files: vec!["/home/jdoe/file".into()],
+            ..Default::default()
+        };
+
+        noop.rename("/home/jdoe/link".as_ref(), "/backup/link".as_ref())
+            .expect("cannot rename symlink");
+        noop.rename("/home/jdoe/file".as_ref(), "/backup/file".as_ref())
+            .expect("cannot rename file");
+
+        assert_eq!(
+            vec![(
+                std::path::PathBuf::from("/backup/link"),
+                std::path::PathBuf::from("/etc/file")
+            )],
+            noop.files
+        );
+        assert_eq!(
+            vec![std::path::PathBuf::from("/backup/file")],
+            noop.regular_files
+        );
+        assert!(noop
+            .rename("/home/jdoe/file".as_ref(), "/backup/file".as_ref())
+            .is_err());
+    }
+
+    #[test]
+    fn noop_canonicalize() {
+        let mut noop = Noop {
+            current_directory: "/home/jdoe".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            std::path::PathBuf::from("/home/jdoe/dotfiles/zsh"),
+            noop.canonicalize("dotfiles/./zsh".as_ref())
+                .expect("cannot canonicalize path")
+        );
+        assert_eq!(
+            std::path::PathBuf::from("/etc/zsh"),
+            noop.canonicalize("/etc/../etc/zsh".as_ref())
+                .expect("cannot canonicalize path")
+        );
+    }
+
+    #[test]
+    fn noop_list_symlinks() {
+        let mut noop = Noop {
+            files: vec![
+                ("/home/jdoe/link".into(), "/etc/file".into()),
+                ("/home/jdoe/subdirectory/link".into(), "/etc/file".into()),
+            ],
+            regular_files: vec!["/home/jdoe/file".into()],
+            ..Default::default()
+        };
+
+        let symlinks = noop
+            .list_symlinks("/home/jdoe".as_ref())
+            .expect("cannot list symlinks");
+
+        assert_eq!(vec![std::path::PathBuf::from("/home/jdoe/link")], symlinks)
+    }
+
+    #[test]
+    fn noop_list_directories() {
+        let mut noop = Noop {
+            directories: vec![
+                "/home/jdoe".into(),
+                "/home/jdoe/.config".into(),
+                "/home/jdoe/.config/nvim".into(),
+            ],
+            ..Default::default()
+        };
+
+        let directories = noop
+            .list_directories("/home/jdoe".as_ref())
+            .expect("cannot list directories");
+
+        assert_eq!(
+            vec![std::path::PathBuf::from("/home/jdoe/.config")],
+            directories
+        )
+    }
+
+    #[test]
+    fn dry_run_do_not_modify() {
+        let mut dry_run = DryRun::new(Noop {
+            files: vec![("/home/jdoe/link".into(), "/etc/file".into())],
+            ..Default::default()
+        });
+        let link = std::path::Path::new("/home/jdoe/link");
+
+        dry_run
+            .create_directory("/home/jdoe/.config".as_ref())
+            .expect("cannot create directory");
+        dry_run
+            .create_symlink(&"/etc/file".into(), &"/home/jdoe/.config/link".into())
+            .expect("cannot create symlink");
+        dry_run.delete_file(link).expect("cannot delete file");
+
+        assert_eq!(
+            std::path::PathBuf::from("/etc/file"),
+            dry_run.read_link(link).expect("cannot read link")
+        );
+        assert!(!dry_run
+            .directory_exists("/home/jdoe/.config".as_ref())
+            .expect("cannot check directory presence"));
+        assert!(!dry_run.is_symlink("/home/jdoe/.config/link".as_ref()));
+    }
+
+    #[test]
+    fn script_noop() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut script = Script::new(
+            &mut output,
+            Noop {
+                files: vec![("/home/jdoe/it's a link".into(), "/etc/file".into())],
+                regular_files: vec!["/home/jdoe/a b".into()],
+                ..Default::default()
+            },
+        );
+
+        script
+            .read_link("/home/jdoe/it's a link".as_ref())
+            .expect("cannot read link");
+        script
+            .delete_file("/home/jdoe/it's a link".as_ref())
+            .expect("cannot delete file");
+        script
+            .create_directory("/home/jdoe/.config".as_ref())
+            .expect("cannot create directory");
+        script
+            .create_symlink(&"../etc/file".into(), &"/home/jdoe/.config/$HOME".into())
+            .expect("cannot create symlink");
+        script
+            .write_file("/home/jdoe/.gitconfig".as_ref(), b"name = 'jdoe'\n")
+            .expect("cannot write file");
+        script
+            .rename(
+                "/home/jdoe/a b".as_ref(),
+                "/home/jdoe/.stow-backup/a b".as_ref(),
+            )
+            .expect("cannot rename file");
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            r#"#!/bin/sh
+set -e
+rm -f -- '/home/jdoe/it'\''s a link'
+mkdir -p -- '/home/jdoe/.config'
+ln -s -- '../etc/file' '/home/jdoe/.config/$HOME'
+printf '%s' 'name = '\''jdoe'\''
+' > '/home/jdoe/.gitconfig'
+mv -- '/home/jdoe/a b' '/home/jdoe/.stow-backup/a b'
+"#,
+            content
+        )
+    }
+
+    #[test]
+    fn script_nothing_to_do() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut script = Script::new(&mut output, Noop::default());
+
+        assert!(!script.is_symlink("/home/jdoe/.zshrc".as_ref()));
+
+        assert!(output.into_inner().unwrap().is_empty())
+    }
+
+    #[test]
+    fn noop_regular_file() {
+        let mut noop = Noop {
+            regular_files: vec!["/home/jdoe/file".into()],
+            ..Default::default()
+        };
+        let file = std::path::Path::new("/home/jdoe/file");
+
+        assert!(noop.file_exists(file).expect("cannot check file presence"));
+        assert!(!noop.is_symlink(file));
+        assert!(noop.read_link(file).is_err());
+
+        noop.delete_file(file).expect("cannot delete file");
+        assert!(!noop.file_exists(file).expect("cannot check file presence"));
+    }
+
     #[test]
     fn filesystem_create_symlink() {
         let ctx = TestWithTempDir::new("create-symlink");
@@ -443,7 +1682,7 @@ mod tests {
         assert!(!src_path.exists(), "directory shouldn't exist");
 
         Filesystem
-            .create_directory(&src_path)
+            .create_directory(src_path)
             .expect("cannot create directory");
 
         assert!(src_path.exists(), "directory should exist");
@@ -494,10 +1733,10 @@ mod tests {
         let ctx = TestWithTempDir::new("file-exists");
         let src = ctx.dir.join("my-file.txt");
         let src_path = src.as_path();
-        std::fs::File::create(&src_path).expect("cannot create temporary file");
+        std::fs::File::create(src_path).expect("cannot create temporary file");
 
         let exists = Filesystem
-            .file_exists(&src_path)
+            .file_exists(src_path)
             .expect("cannot check file presence");
 
         assert!(exists, "file should exist");
@@ -510,7 +1749,7 @@ mod tests {
         let src_path = src.as_path();
 
         let exists = Filesystem
-            .file_exists(&src_path)
+            .file_exists(src_path)
             .expect("cannot check file presence");
 
         assert!(!exists, "file shouldn't exist");
@@ -523,7 +1762,7 @@ mod tests {
         let src_path = src.as_path();
         std::fs::create_dir(&src).expect("cannot create temporary directory");
 
-        let err = Filesystem.file_exists(&src_path).unwrap_err();
+        let err = Filesystem.file_exists(src_path).unwrap_err();
 
         assert_eq!(
             Error::Generic(format!(
@@ -548,7 +1787,7 @@ mod tests {
         std::os::unix::fs::symlink(&src, &dest).expect("cannot create symlink");
 
         let link_target = Filesystem
-            .read_link(&dest_path)
+            .read_link(dest_path)
             .expect("cannot read symlink");
 
         assert_eq!(src_path, link_target)
@@ -560,7 +1799,7 @@ mod tests {
         let dest = ctx.dir.join("mylink.txt");
         let dest_path = dest.as_path();
 
-        let err = Filesystem.read_link(&dest_path).unwrap_err();
+        let err = Filesystem.read_link(dest_path).unwrap_err();
 
         assert_eq!(
             Error::ReadFile(ReadFileError {
@@ -576,22 +1815,134 @@ mod tests {
         let ctx = TestWithTempDir::new("delete-file");
         let src = ctx.dir.join("my-file.txt");
         let src_path = src.as_path();
-        std::fs::File::create(&src_path).expect("cannot create temporary file");
+        std::fs::File::create(src_path).expect("cannot create temporary file");
 
         assert!(src_path.exists(), "file should exist");
         Filesystem
-            .delete_file(&src_path)
+            .delete_file(src_path)
             .expect("cannot delete file");
         assert!(!src_path.exists(), "file shouldn't exist");
     }
 
+    #[test]
+    fn filesystem_write_file() {
+        let ctx = TestWithTempDir::new("write-file");
+        let file = ctx.dir.join("my-file.txt");
+
+        Filesystem
+            .write_file(&file, b"content")
+            .expect("cannot write file");
+        assert_eq!(
+            b"content".to_vec(),
+            Filesystem.read_file(&file).expect("cannot read file")
+        );
+
+        let err = Filesystem.write_file(&file, b"other").unwrap_err();
+        assert_eq!(
+            Error::WriteFile(WriteFileError {
+                file: file.display().to_string(),
+                reason: "File exists (os error 17)".to_string(),
+            }),
+            err
+        );
+    }
+
+    #[test]
+    fn filesystem_copy_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let ctx = TestWithTempDir::new("copy-file");
+        let source = ctx.dir.join("script.sh");
+        let destination = ctx.dir.join("copy.sh");
+        std::fs::write(&source, b"#!/bin/sh\n").expect("cannot write file");
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o755))
+            .expect("cannot set permissions");
+
+        Filesystem
+            .copy_file(&source, &destination)
+            .expect("cannot copy file");
+        assert_eq!(
+            b"#!/bin/sh\n".to_vec(),
+            Filesystem
+                .read_file(&destination)
+                .expect("cannot read file")
+        );
+        assert_eq!(
+            0o755,
+            std::fs::metadata(&destination)
+                .expect("cannot read metadata")
+                .permissions()
+                .mode()
+                & 0o777
+        );
+
+        let err = Filesystem.copy_file(&source, &destination).unwrap_err();
+        assert_eq!(
+            Error::CopyFile(CopyFileError {
+                source: source.display().to_string(),
+                destination: destination.display().to_string(),
+                reason: "File exists (os error 17)".to_string(),
+            }),
+            err
+        );
+    }
+
+    #[test]
+    fn filesystem_create_hardlink() {
+        let ctx = TestWithTempDir::new("create-hardlink");
+        let source = ctx.dir.join("file.txt");
+        let destination = ctx.dir.join("link.txt");
+        std::fs::write(&source, b"content").expect("cannot write file");
+
+        assert!(!Filesystem
+            .is_same_file(&destination, &source)
+            .expect("cannot compare files"));
+        Filesystem
+            .create_hardlink(&source, &destination)
+            .expect("cannot link file");
+        assert!(Filesystem
+            .is_same_file(&destination, &source)
+            .expect("cannot compare files"));
+        assert!(Filesystem
+            .is_same_filesystem(&destination, &ctx.dir)
+            .expect("cannot compare filesystems"));
+
+        std::fs::remove_file(&destination).expect("cannot remove file");
+        std::fs::write(&destination, b"content").expect("cannot write file");
+        assert!(!Filesystem
+            .is_same_file(&destination, &source)
+            .expect("cannot compare files"));
+    }
+
+    #[test]
+    fn filesystem_remove_directory() {
+        let ctx = TestWithTempDir::new("remove-directory");
+        let directory = ctx.dir.join("directory");
+        std::fs::create_dir(&directory).expect("cannot create directory");
+        std::fs::File::create(directory.join("file")).expect("cannot create file");
+
+        assert_eq!(
+            vec![directory.join("file")],
+            Filesystem
+                .read_directory(&directory)
+                .expect("cannot read directory")
+        );
+        assert!(Filesystem.remove_directory(&directory).is_err());
+
+        std::fs::remove_file(directory.join("file")).expect("cannot remove file");
+        Filesystem
+            .remove_directory(&directory)
+            .expect("cannot remove directory");
+        assert!(!directory.exists(), "directory shouldn't exist");
+    }
+
     #[test]
     fn filesystem_delete_file_do_not_exist() {
         let ctx = TestWithTempDir::new("delete-file");
         let src = ctx.dir.join("my-file.txt");
         let src_path = src.as_path();
 
-        let err = Filesystem.delete_file(&src_path).unwrap_err();
+        let err = Filesystem.delete_file(src_path).unwrap_err();
 
         assert_eq!(
             Error::DeleteFile(DeleteFileError {
@@ -602,16 +1953,92 @@ mod tests {
         )
     }
 
-    struct TestWithTempDir {
-        dir: std::path::PathBuf,
+    #[test]
+    fn filesystem_list_directories() {
+        let ctx = TestWithTempDir::new("list_directories");
+        std::fs::create_dir(ctx.dir.join("directory")).expect("cannot create directory");
+        std::fs::File::create(ctx.dir.join("file")).expect("cannot create file");
+        std::os::unix::fs::symlink(ctx.dir.join("directory"), ctx.dir.join("link"))
+            .expect("cannot create symlink");
+
+        let directories = Filesystem
+            .list_directories(&ctx.dir)
+            .expect("cannot list directories");
+
+        assert_eq!(vec![ctx.dir.join("directory")], directories)
+    }
+
+    #[test]
+    fn filesystem_rename() {
+        let ctx = TestWithTempDir::new("rename");
+        let src = ctx.dir.join("my-file.txt");
+        let dest = ctx.dir.join("my-file.txt.bak");
+        std::fs::File::create(&src).expect("cannot create temporary file");
+
+        Filesystem.rename(&src, &dest).expect("cannot rename file");
+
+        assert!(!src.exists(), "file shouldn't exist");
+        assert!(dest.exists(), "file should exist");
+    }
+
+    #[test]
+    fn filesystem_run_script() {
+        let ctx = TestWithTempDir::new("script");
+        let directory = ctx.dir.join("it's a \"directory\"");
+        let link = directory.join("$link `here`");
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut script = Script::new(&mut output, DryRun::new(Filesystem));
+
+        script
+            .create_directory(&directory)
+            .expect("cannot create directory");
+        script
+            .create_symlink(&"../target\nfile".into(), &link.as_path().into())
+            .expect("cannot create symlink");
+        assert!(!directory.exists(), "directory shouldn't exist yet");
+
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(String::from_utf8(output.into_inner().unwrap()).unwrap())
+            .status()
+            .expect("cannot run script");
+
+        assert!(status.success());
+        assert_eq!(
+            std::path::PathBuf::from("../target\nfile"),
+            std::fs::read_link(&link).expect("cannot read link")
+        );
+    }
+
+    #[test]
+    fn filesystem_rename_do_not_exist() {
+        let ctx = TestWithTempDir::new("rename");
+        let src = ctx.dir.join("my-file.txt");
+        let dest = ctx.dir.join("my-file.txt.bak");
+
+        let err = Filesystem.rename(&src, &dest).unwrap_err();
+
+        assert_eq!(
+            Error::RenameFile(RenameFileError {
+                source: src.display().to_string(),
+                destination: dest.display().to_string(),
+                reason: "No such file or directory (os error 2)".to_string(),
+            }),
+            err
+        )
+    }
+
+    pub(crate) struct TestWithTempDir {
+        pub(crate) dir: std::path::PathBuf,
     }
 
     impl TestWithTempDir {
         pub fn new(basename: &str) -> Self {
             let mut tmpdir = std::env::temp_dir();
-            tmpdir.push(format!("{}-{}", basename, uuid::Uuid::new_v4().to_string()));
-            std::fs::create_dir(&tmpdir)
-                .expect(format!("cannot create temporary directory {}", tmpdir.display()).as_str());
+            tmpdir.push(format!("{}-{}", basename, uuid::Uuid::new_v4()));
+            std::fs::create_dir(&tmpdir).unwrap_or_else(|_| {
+                panic!("cannot create temporary directory {}", tmpdir.display())
+            });
             Self { dir: tmpdir }
         }
     }
@@ -1,7 +1,15 @@
 use clap::Parser;
 use stow::command;
+use stow::config;
+use stow::event;
+use stow::ignore;
 use stow::linker;
+use stow::package;
 use stow::path;
+use stow::plan;
+use stow::state;
+use stow::template;
+use stow::variant;
 use stow::writer;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -43,102 +51,365 @@ struct Cli {
         long_help = DELETE_LONG_HELP,
     )]
     delete: bool,
+    #[arg(
+        short = 'R',
+        long = None,
+        conflicts_with = "delete",
+        help = RESTOW_SHORT_HELP,
+        long_help = RESTOW_LONG_HELP,
+    )]
+    restow: bool,
+    #[arg(
+        long = "status",
+        conflicts_with_all = ["delete", "restow"],
+        help = STATUS_SHORT_HELP,
+        long_help = STATUS_LONG_HELP,
+    )]
+    status: bool,
+    #[arg(
+        long = "prune",
+        conflicts_with_all = ["delete", "restow", "status", "all", "packages"],
+        help = PRUNE_SHORT_HELP,
+        long_help = PRUNE_LONG_HELP,
+    )]
+    prune: bool,
+    #[arg(
+        long = "prune-depth",
+        value_name = "DEPTH",
+        default_value = "5",
+        help = PRUNE_DEPTH_SHORT_HELP,
+        long_help = PRUNE_DEPTH_LONG_HELP,
+    )]
+    prune_depth: usize,
     #[arg(
         short = 'd',
         long = None,
-        default_value=".",
         env = "STOW_DIR",
         help = SOURCE_SHORT_HELP,
         long_help = SOURCE_LONG_HELP,
     )]
-    source_directory: String,
+    source_directory: Option<String>,
     #[arg(
         short = 't',
         long = None,
-        env = "HOME",
         help = TARGET_SHORT_HELP,
         long_help=TARGET_LONG_HELP,
     )]
-    target_directory: String,
+    target_directory: Option<String>,
+    #[arg(
+        long = "conflict",
+        value_name = "POLICY",
+        help = CONFLICT_SHORT_HELP,
+        long_help = CONFLICT_LONG_HELP,
+    )]
+    conflict: Option<plan::ConflictPolicy>,
+    #[arg(
+        long = "adopt",
+        conflicts_with = "conflict",
+        help = ADOPT_SHORT_HELP,
+        long_help = ADOPT_LONG_HELP,
+    )]
+    adopt: bool,
+    #[arg(
+        long = "restore",
+        requires = "delete",
+        help = RESTORE_SHORT_HELP,
+        long_help = RESTORE_LONG_HELP,
+    )]
+    restore: bool,
+    #[arg(
+        long = "absolute",
+        help = ABSOLUTE_SHORT_HELP,
+        long_help = ABSOLUTE_LONG_HELP,
+    )]
+    absolute: bool,
+    #[arg(
+        long = "no-folding",
+        help = NO_FOLDING_SHORT_HELP,
+        long_help = NO_FOLDING_LONG_HELP,
+    )]
+    no_folding: bool,
+    #[arg(
+        long = "copy",
+        help = COPY_SHORT_HELP,
+        long_help = COPY_LONG_HELP,
+    )]
+    copy: bool,
+    #[arg(
+        long = "hardlink",
+        conflicts_with = "copy",
+        help = HARDLINK_SHORT_HELP,
+        long_help = HARDLINK_LONG_HELP,
+    )]
+    hardlink: bool,
+    #[arg(
+        long = "dotfiles",
+        help = DOTFILES_SHORT_HELP,
+        long_help = DOTFILES_LONG_HELP,
+    )]
+    dotfiles: bool,
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        help = TAG_SHORT_HELP,
+        long_help = TAG_LONG_HELP,
+    )]
+    tags: Vec<String>,
     #[arg(short = 'n', help = DRY_RUN_SHORT_HELP, long_help=DRY_RUN_LONG_HELP)]
     dry_run: bool,
+    #[arg(
+        long = "script",
+        requires = "dry_run",
+        conflicts_with_all = ["format", "status", "list"],
+        help = SCRIPT_SHORT_HELP,
+        long_help = SCRIPT_LONG_HELP,
+    )]
+    script: bool,
     #[arg(
         short = 'v',
-        default_value = "1",
         value_parser=parse_verbosity,
         help = VERBOSITY_SHORT_HELP,
         long_help = VERSBOSITY_LONG_HELP
     )]
-    verbosity: Verbosity,
+    verbosity: Option<Verbosity>,
+    #[arg(
+        long = "format",
+        value_name = "FORMAT",
+        default_value = "text",
+        help = FORMAT_SHORT_HELP,
+        long_help = FORMAT_LONG_HELP,
+    )]
+    format: event::Format,
+    #[arg(
+        long = "all",
+        conflicts_with = "packages",
+        help = ALL_SHORT_HELP,
+        long_help = ALL_LONG_HELP,
+    )]
+    all: bool,
+    #[arg(
+        long = "list",
+        conflicts_with_all = ["all", "packages"],
+        help = LIST_SHORT_HELP,
+        long_help = LIST_LONG_HELP,
+    )]
+    list: bool,
+    #[arg(
+        long = "profile",
+        conflicts_with_all = ["all", "packages", "prune"],
+        help = PROFILE_SHORT_HELP,
+        long_help = PROFILE_LONG_HELP,
+    )]
+    profile: Option<String>,
     #[arg(help = PACKAGES_SHORT_HELP, long_help=PACKAGES_LONG_HELP)]
     packages: Vec<String>,
 }
 
 fn main() -> Result<(), stow::Error> {
     let cli = Cli::parse();
+    let format = cli.format;
+
+    let result = run(cli);
+    if let (event::Format::Json, Err(err)) = (format, &result) {
+        println!("{}", event::Event::error(err));
+        std::process::exit(1);
+    }
 
-    let source_directory: path::Source = cli.source_directory.as_str().into();
-    let destination_directory: path::Destination = cli.target_directory.as_str().into();
+    result
+}
+
+fn run(cli: Cli) -> Result<(), stow::Error> {
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from);
+    let global_config = match &home {
+        Some(home) => config::Config::load(&home.join(config::GLOBAL_CONFIG_FILE), Some(home))?,
+        None => config::Config::default(),
+    };
+
+    let source_directory = cli
+        .source_directory
+        .as_ref()
+        .filter(|directory| !directory.is_empty())
+        .map(std::path::PathBuf::from)
+        .or_else(|| global_config.source.clone())
+        .unwrap_or_else(|| ".".into());
+    let config = global_config.merge(config::Config::load(
+        &source_directory.join(config::LOCAL_CONFIG_FILE),
+        home.as_deref(),
+    )?);
+    let source_directory: path::Source = source_directory.as_path().into();
+    let profile = cli
+        .profile
+        .as_ref()
+        .map(|name| config.profile(name))
+        .transpose()?;
+
+    let target_directory = cli
+        .target_directory
+        .as_ref()
+        .map(std::path::PathBuf::from)
+        .or_else(|| profile.and_then(|p| p.target.clone()))
+        .or_else(|| config.target.clone())
+        .or_else(|| home.clone())
+        .ok_or_else(|| stow::Error::Generic("no target directory given".to_string()))?;
+    let target_directory = std::path::absolute(&target_directory).map_err(|e| {
+        stow::Error::Generic(format!(
+            "invalid target directory {}: {}",
+            target_directory.display(),
+            e
+        ))
+    })?;
+    let destination_directory: path::Destination = target_directory.as_path().into();
+
+    let verbosity = match (cli.verbosity, config.verbosity) {
+        (Some(verbosity), _) => verbosity,
+        (None, Some(verbosity)) => parse_verbosity(&verbosity.to_string())
+            .map_err(|e| stow::Error::Generic(format!("invalid configuration: {}", e)))?,
+        (None, None) => Verbosity::WarningOnly,
+    };
+
+    let global_ignore = match &home {
+        Some(home) => ignore::Ignore::default().load(&home.join(ignore::GLOBAL_IGNORE_FILE))?,
+        None => ignore::Ignore::default(),
+    }
+    .extend(&config.ignored()?);
+    let packages = match profile {
+        Some(profile) => config.expand(profile.packages.clone())?,
+        None => config.expand(cli.packages)?,
+    };
+
+    if cli.list {
+        for p in package::list(&source_directory, &global_ignore)? {
+            match cli.format {
+                event::Format::Text => println!("{}", p),
+                event::Format::Json => {
+                    println!(
+                        "{}",
+                        event::Event::new(event::Kind::Package).with_package(&p)
+                    )
+                }
+            }
+        }
+        return Ok(());
+    }
 
     let stderr = std::io::stderr();
-    let mut link: Box<dyn linker::Linker> = if cli.dry_run {
-        Box::new(linker::Verbose::new(&stderr, linker::Noop::default()))
-    } else if cli.verbosity == Verbosity::Verbose {
+    let mut link: Box<dyn linker::Linker> = if cli.script {
+        Box::new(linker::Script::new(
+            std::io::stdout(),
+            linker::DryRun::new(linker::Filesystem),
+        ))
+    } else if cli.format == event::Format::Json {
+        if cli.dry_run {
+            Box::new(linker::DryRun::new(linker::Filesystem))
+        } else {
+            Box::new(linker::Filesystem)
+        }
+    } else if cli.dry_run {
+        Box::new(linker::Verbose::new(
+            &stderr,
+            linker::DryRun::new(linker::Filesystem),
+        ))
+    } else if verbosity == Verbosity::Verbose {
         Box::new(linker::Verbose::new(&stderr, linker::Filesystem))
     } else {
         Box::new(linker::Filesystem)
     };
 
-    let command_logger: Box<dyn std::io::Write> = if cli.verbosity == Verbosity::Silent {
+    let command_logger: Box<dyn std::io::Write> = if verbosity == Verbosity::Silent {
         Box::new(writer::Noop)
+    } else if cli.format == event::Format::Json {
+        Box::new(std::io::stdout())
     } else {
         Box::new(&stderr)
     };
 
-    let mut cmd = command::Command::new(command_logger, link.as_mut());
-    if cli.delete {
-        cmd.unstow(&source_directory, &destination_directory, cli.packages)?;
-    } else {
-        cmd.stow(&source_directory, &destination_directory, cli.packages)?;
-    }
-
-    Ok(())
-}
+    let machine = variant::Machine::current()
+        .with_tags(&config.tags)
+        .with_tags(profile.map(|p| p.tags.as_slice()).unwrap_or_default())
+        .with_tags(&cli.tags);
+    let variables = match &home {
+        Some(home) => {
+            template::Variables::new(&machine).load(&home.join(template::VARIABLES_FILE))?
+        }
+        None => template::Variables::new(&machine),
+    };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    let options = command::Options {
+        conflict_policy: if cli.adopt {
+            plan::ConflictPolicy::Adopt
+        } else {
+            cli.conflict.or(config.conflict).unwrap_or_default()
+        },
+        restore_backups: cli.restore,
+        absolute: cli.absolute,
+        no_folding: cli.no_folding,
+        ignore: global_ignore.clone(),
+        dotfiles: cli.dotfiles,
+        machine,
+        variables,
+        copy: cli.copy,
+        hardlink: cli.hardlink,
+        format: cli.format,
+        dry_run: cli.dry_run,
+    };
 
-    #[test]
-    fn parse_invalid_verbosity() {
-        let testcases = vec![
-            ("-1", "negative value"),
-            ("quiet", "string value"),
-            ("5", "out of range value"),
-        ];
+    let run_packages = |cmd: &mut command::Command<Box<dyn std::io::Write>, dyn linker::Linker>,
+                        packages: Vec<String>| {
+        if cli.delete {
+            cmd.unstow(&source_directory, &destination_directory, packages)
+        } else if cli.restow {
+            cmd.restow(&source_directory, &destination_directory, packages)
+        } else {
+            cmd.stow(&source_directory, &destination_directory, packages)
+        }
+    };
 
-        for (value, reason) in testcases {
-            assert_eq!(
-                Err(Error),
-                parse_verbosity(value),
-                "the parser did not fail to parse {}",
-                reason
-            )
+    let state_file = source_directory.join(state::STATE_FILE);
+    let mut cmd = command::Command::new(command_logger, link.as_mut())
+        .with_options(options)
+        .with_state(state::State::load(&state_file)?);
+
+    if cli.status {
+        let packages = if cli.all {
+            package::list(&source_directory, &global_ignore)?
+        } else {
+            packages
+        };
+        let report = cmd.status(&source_directory, &destination_directory, &packages)?;
+        match cli.format {
+            event::Format::Text => print!("{}", report),
+            event::Format::Json => {
+                for entry in report.entries.iter() {
+                    println!("{}", event::Event::status(entry));
+                }
+            }
         }
+        return Ok(());
     }
 
-    #[test]
-    fn parse_valid_verbosity() {
-        let testcases = vec![
-            ("0", Verbosity::Silent),
-            ("1", Verbosity::WarningOnly),
-            ("2", Verbosity::Verbose),
-        ];
-
-        for (value, expected) in testcases {
-            assert_eq!(Ok(expected), parse_verbosity(value))
+    let result = if cli.prune {
+        cmd.prune(&source_directory, &destination_directory, cli.prune_depth)
+    } else if let Some(name) = &cli.profile {
+        if cli.delete {
+            cmd.remove_profile(&source_directory, &destination_directory, packages)
+        } else {
+            cmd.apply_profile(&source_directory, &destination_directory, name, packages)
         }
+    } else if cli.all {
+        let packages = package::list(&source_directory, &global_ignore)?;
+        cmd.for_each_package(packages, run_packages)
+    } else {
+        run_packages(&mut cmd, packages)
+    };
+
+    // the state only changes once a plan is executed, so it is left as is
+    // when planning failed, and kept up to date with the actions executed
+    // before a failure
+    if !cli.dry_run {
+        cmd.state().save(&state_file)?;
     }
+
+    result
 }
 
 static ABOUT_LONG_HELP: &str =
@@ -146,43 +417,286 @@ static ABOUT_LONG_HELP: &str =
 target directory
 
 It helps manage packages individually while still being able to install them in a
-shared file tree.";
+shared file tree.
+
+Default options are read from ~/.config/stow/config.toml, then from
+STOW_DIR/.stowrc, the command line flags taking precedence over both. These TOML
+files accept the following keys:
+source = \"~/dotfiles\"       # stow directory, only in ~/.config/stow/config.toml
+target = \"~\"                # target directory
+verbosity = 1               # from quiet(0) to chatty(2)
+conflict = \"backup\"         # conflict policy
+ignore = ['.*\\.swp']        # patterns to ignore in every package
+tags = [\"work\"]             # tags selecting the package file variants
+[groups]
+work = [\"zsh\", \"git\"]       # packages stowed with @work
+[profiles.work-mac]         # profile applied with --profile work-mac
+packages = [\"@work\"]
+Relative paths are resolved from the directory of the configuration file. A
+.stowrc file of GNU stow, made of command line flags, is ignored.";
 
 static DELETE_SHORT_HELP: &str = "Delete all the symlinks pointing to the targeted packages";
 
 static DELETE_LONG_HELP: &str =
     "Tries to remove all the symlinks belonging to the targeted packages.
 
-For each package, gather all the directories containing configuration. In the
-targeted directory, remove all symlinks stored in directories named after the
-directories collected in the previous step, if they target the package.";
+For each file of the package, remove the symlink standing in its place in the
+targeted directory, only if it targets that exact file. Any other file found in
+its place is left untouched and reported. The directories left empty are
+removed, except the target directory itself.
+
+The links created by stow are also recorded in STOW_DIR/.stow-state, so they are
+removed even once the package has been deleted or renamed, as long as they still
+target the recorded file.";
+
+static ABSOLUTE_SHORT_HELP: &str = "Create symlinks targeting absolute paths";
+
+static ABSOLUTE_LONG_HELP: &str =
+    "Create symlinks targeting the absolute path of the package files instead of a
+path relative to the directory containing the symlink.";
+
+static DOTFILES_SHORT_HELP: &str = "Link files prefixed with dot- as hidden files";
+
+static DOTFILES_LONG_HELP: &str =
+    "Link the package files and directories whose name starts with dot- under a name
+starting with a dot instead, e.g. zsh/dot-zshrc is linked as ~/.zshrc and
+nvim/dot-config/nvim as ~/.config/nvim.";
+
+static NO_FOLDING_SHORT_HELP: &str = "Create directories instead of linking them";
+
+static NO_FOLDING_LONG_HELP: &str =
+    "Always create the package directories in the target directory and link each
+file, instead of linking a whole directory when a single package provides it.";
+
+static COPY_SHORT_HELP: &str = "Copy the package files instead of linking them";
+
+static COPY_LONG_HELP: &str =
+    "Copy the package files instead of linking them, for the programs which don't
+work with symlinks. Copies are removed when unstowing, unless they have been
+modified since.
+
+Only some files of a package can be copied by listing them in the
+.stow-local-copy file of the package, with the same syntax as the ignore files.
+Listing a directory copies all its files.";
+
+static HARDLINK_SHORT_HELP: &str = "Create hardlinks instead of symlinks";
+
+static HARDLINK_LONG_HELP: &str =
+    "Create hardlinks instead of symlinks, for the programs which refuse symlinks.
+Directories are never linked as a whole, and the STOW_DIR must be on the same
+filesystem as the target directory. Hardlinks are removed when unstowing as long
+as they are still the same file as the package file.";
+
+static ALL_SHORT_HELP: &str = "Target all the packages of the STOW_DIR";
+
+static ALL_LONG_HELP: &str =
+    "Target all the packages of the STOW_DIR instead of the ones given as arguments.
+Each package is handled on its own and its outcome is reported, a failing package
+doesn't prevent the other ones from being handled.";
+
+static LIST_SHORT_HELP: &str = "List the packages of the STOW_DIR";
+
+static LIST_LONG_HELP: &str =
+    "Print the name of all the packages of the STOW_DIR, one per line, and exit.
+Directories matching an ignore pattern are not listed.";
+
+static ADOPT_SHORT_HELP: &str = "Move existing files into the packages before linking them";
+
+static ADOPT_LONG_HELP: &str = "Shortcut for --conflict=adopt.
+
+When a regular file is in the way of a link, it is moved into the package,
+replacing the file the package provides, and a symlink is created in its place.
+Use a version control system on the STOW_DIR to review the adopted changes.";
+
+static CONFLICT_SHORT_HELP: &str = "What to do when a file is in the way of a link";
+
+static CONFLICT_LONG_HELP: &str =
+    "What to do when a regular file, or a symlink which doesn't point to the STOW_DIR,
+is in the way of a link:
+abort: do not change anything and report all the conflicts
+skip: leave the file in place and do not link it
+overwrite: delete the file and link it
+backup: move the file to $TARGET/.stow-backup/<package>/ and link it, a number
+  being appended to its name when an older backup is in the way
+adopt: move the file into the package, replacing the package version, and link it
+
+A summary of all the conflicts is printed once done. The policy defaults to abort.";
+
+static TAG_SHORT_HELP: &str = "Use the package file variants matching the tag";
+
+static TAG_LONG_HELP: &str = "Use the package file variants matching the tag. It can be repeated.
+
+A package file can come in several variants, named after the file followed by
+## and a comma separated list of conditions which must all match the machine:
+os.<name> (e.g. linux or macos), host.<name>, user.<name> or tag.<name>, the
+tags being the ones given by this flag. The default condition always matches.
+The most specific matching variant is linked under the name of the file, e.g.
+gitconfig##host.work-vm as gitconfig. A host is more specific than a user, then a
+tag, then an OS.";
+
+static FORMAT_SHORT_HELP: &str = "Set the output format, text or json";
+
+static FORMAT_LONG_HELP: &str = "text: print warnings and conflicts as free text to STDERR
+json: print to STDOUT one JSON object per line for each action, warning,
+conflict and error, e.g.
+{\"type\":\"create-symlink\",\"package\":\"zsh\",\"source\":\"dotfiles/zsh/.zshrc\",
+\"destination\":\"/home/jdoe/.zshrc\",\"outcome\":\"done\"}
+The type is the action taken (create-directory, create-symlink,
+create-hardlink, delete-file, remove-directory, rename, backup, write-file,
+copy-file), or conflict, warning, error, package or status. The outcome of an
+action is done, planned with -n, failed, or aborted when conflicts prevent the
+whole plan from being executed. The package, source, destination, outcome and
+message fields are left out when they don't apply. Only -v 0 silences the
+events.";
 
 static DRY_RUN_SHORT_HELP: &str = "Do not execute the program, only print commands";
 
 static DRY_RUN_LONG_HELP: &str = "Do not execute the program, only print commands.";
 
+static SCRIPT_SHORT_HELP: &str = "With -n, print the changes as a shell script";
+
+static SCRIPT_LONG_HELP: &str =
+    "With -n, print to STDOUT a POSIX shell script making the changes instead of the
+commands, e.g.
+#!/bin/sh
+set -e
+mkdir -p -- '/home/jdoe/.config'
+ln -s -- '../dotfiles/zsh/.zshrc' '/home/jdoe/.zshrc'
+Only the commands modifying the target directory are printed, with their
+arguments quoted, so the script can be reviewed and run on another machine.
+Nothing is printed when there is nothing to do. Warnings and conflicts are
+still printed to STDERR. The links created by the script are not recorded in
+STOW_DIR/.stow-state.";
+
+static PROFILE_SHORT_HELP: &str = "Apply the packages of a profile defined in the configuration";
+
+static PROFILE_LONG_HELP: &str =
+    "Target the packages of a profile defined in the configuration files instead of
+the ones given as arguments, using the tags and target directory of the profile:
+[profiles.work-mac]
+packages = [\"@work\", \"vim\"]
+tags = [\"work\", \"mac\"]
+target = \"~\"
+
+The profile is restowed, and the packages of the profile previously applied to
+the target directory which are not part of it are unstowed, as a single step. The
+profile applied to each target directory is recorded in STOW_DIR/.stow-state.
+With -D, the packages of the profile and of the applied one are unstowed.";
+
 static PACKAGES_SHORT_HELP: &str = "All the packages to install on or remove from the system";
 
 static PACKAGES_LONG_HELP: &str =
     "Packages are all the directories placed at the root of the STOW_DIR.
 The content of these packages are all files and directories below these top level
-directories. They will be copied verbatim to the target directory.";
+directories. They will be copied verbatim to the target directory.
+
+A package can be followed by the path of one of its files or directories (e.g.
+zsh/zshrc.mac) to only use this part of the package. It is placed in the target
+directory at the same location as when the whole package is used.
+
+A package named @<group> stands for all the packages of the group, as defined in
+the configuration files.
+
+Files ending with .tmpl are templates: they are rendered without their suffix in
+the target directory, as regular files, replacing {{ host }}, {{ os }}, {{ user }}
+and the variables defined in ~/.config/stow/vars.toml. A rendered file modified
+since is never overwritten nor removed, like the copies made by --copy.
+
+Files whose name matches a pattern listed in the .stow-local-ignore file of the
+package, or in ~/.stow-global-ignore, are skipped. Patterns are regular
+expressions matched against the file name, or against the path from the package
+root when they contain a /. Version control files and .stow-* files are always
+skipped.";
+
+static RESTOW_SHORT_HELP: &str = "Remove stale symlinks and stow the targeted packages again";
+
+static RESTOW_LONG_HELP: &str =
+    "Remove the symlinks pointing to files which are not part of the targeted packages
+anymore, then stow the packages. Both steps are computed before changing anything
+so that a conflict leaves the target directory untouched.";
+
+static RESTORE_SHORT_HELP: &str = "Restore the files backed up when stowing";
+
+static RESTORE_LONG_HELP: &str =
+    "When a symlink is removed, move back the file which was in its way when the
+package was stowed with the backup conflict policy. Only the backups recorded in
+STOW_DIR/.stow-state are restored, and the backup directories left empty are
+removed.";
+
+static PRUNE_SHORT_HELP: &str = "Remove the broken symlinks pointing to the STOW_DIR";
+
+static PRUNE_LONG_HELP: &str =
+    "Scan the target directory for symlinks pointing inside the STOW_DIR to files which
+don't exist anymore, e.g. the files of a deleted package, and remove them. The
+directories below the target directory which cannot be read are skipped with a
+warning.";
+
+static PRUNE_DEPTH_SHORT_HELP: &str = "How deep --prune scans the target directory";
+
+static PRUNE_DEPTH_LONG_HELP: &str =
+    "Number of directory levels below the target directory scanned by --prune, 0
+scanning only the target directory itself.";
+
+static STATUS_SHORT_HELP: &str = "Print the state of the links of the targeted packages";
+
+static STATUS_LONG_HELP: &str =
+    "Print, without changing anything, how many files of each targeted package are
+linked, missing, linking to another file, blocked by an existing file or broken
+links, followed by the list of the files which are not linked.";
 
 static SOURCE_SHORT_HELP: &str = "Set the directory where packages can be found";
 
 static SOURCE_LONG_HELP: &str = "This is the directory where packages can be found.
-Set the stow directory instead of using the STOW_DIR environment variable or the
-current directory.";
+Set the stow directory instead of using the STOW_DIR environment variable, the
+source of the configuration file or the current directory.";
 
 static TARGET_SHORT_HELP: &str = "Set the directory where files will be placed";
 
 static TARGET_LONG_HELP: &str =
     "Target is the root directory where the content of the packages will be symlinked
 to. If a directory required by a package doesn't exist, it will be created
-automatically.";
+automatically. It defaults to the target of the configuration files, or to the
+home directory.";
 
 static VERBOSITY_SHORT_HELP: &str = "From quiet(0) to chatty(2)";
 
 static VERSBOSITY_LONG_HELP: &str = "0: do not print anything to STDERR
-1: print only when the program will override a file or a symlink
-2: print all commands the program will execute to STDERR";
+1: print only warnings and conflicts
+2: print all commands the program will execute to STDERR
+It defaults to 1.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_invalid_verbosity() {
+        let testcases = vec![
+            ("-1", "negative value"),
+            ("quiet", "string value"),
+            ("5", "out of range value"),
+        ];
+
+        for (value, reason) in testcases {
+            assert_eq!(
+                Err(Error),
+                parse_verbosity(value),
+                "the parser did not fail to parse {}",
+                reason
+            )
+        }
+    }
+
+    #[test]
+    fn parse_valid_verbosity() {
+        let testcases = vec![
+            ("0", Verbosity::Silent),
+            ("1", Verbosity::WarningOnly),
+            ("2", Verbosity::Verbose),
+        ];
+
+        for (value, expected) in testcases {
+            assert_eq!(Ok(expected), parse_verbosity(value))
+        }
+    }
+}
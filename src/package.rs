@@ -1,37 +1,183 @@
-use crate::{path, Error, ReadFileError};
+use crate::{ignore, path, Error, ReadFileError};
 
+/// Name of the file listing the patterns of the package files to copy instead
+/// of linking them, with the same syntax as the ignore files.
+pub const COPY_FILE: &str = ".stow-local-copy";
+
+/// Package of the source directory, or only part of it when created from a
+/// path such as `zsh/zshrc.mac`. In that case, only the files below the
+/// sub-path are read, still relative to the package root.
 #[derive(Debug)]
 pub struct Package<'a> {
     path: std::path::PathBuf,
     name: &'a str,
+    subpath: &'a str,
+    ignore: ignore::Ignore,
+    copy: ignore::Ignore,
 }
 
 impl<'a> Package<'a> {
     pub fn new(src_dir: &path::Source, name: &'a str) -> Result<Self, Error> {
+        let (name, subpath) = split(name);
         let path = src_dir.join(name);
+        if !path.exists() || !path.join(subpath).exists() {
+            return Err(Error::PackageNotFound(join(name, subpath)));
+        }
+
+        let ignore = ignore::Ignore::builtin().load(&path.join(ignore::LOCAL_IGNORE_FILE))?;
+        let copy = ignore::Ignore::default().load(&path.join(COPY_FILE))?;
+
+        Ok(Self {
+            name,
+            subpath,
+            path,
+            ignore,
+            copy,
+        })
+    }
+
+    /// Name of the package, without the sub-path it was created from.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
 
-        path.exists()
-            .then_some(Self { name, path })
-            .ok_or_else(|| Error::PackageNotFound(name.to_string()))
+    /// Path, relative to the package root, of the only part of the package to
+    /// read. It is empty when the whole package is read.
+    pub fn subpath(&self) -> &'a str {
+        self.subpath
     }
 
-    pub fn read_dirs(&self) -> Result<PackageIterator, Error> {
+    fn root(&self) -> std::path::PathBuf {
+        self.path.join(self.subpath)
+    }
+
+    /// Ignores the files matching the patterns of `ignore` on top of the ones
+    /// defined by the package.
+    pub fn with_ignore(mut self, ignore: &ignore::Ignore) -> Self {
+        self.ignore = self.ignore.extend(ignore);
+        self
+    }
+
+    pub fn read_dirs(&self) -> Result<PackageIterator<'_>, Error> {
         Ok(PackageIterator {
             package: self,
-            readdir: walkdir::WalkDir::new(&self.path).into_iter(),
+            readdir: walkdir::WalkDir::new(self.root())
+                .sort_by_file_name()
+                .into_iter(),
             should_keep: |p| p.is_dir(),
         })
     }
 
-    pub fn read_files(&self) -> Result<PackageIterator, Error> {
+    pub fn read_files(&self) -> Result<PackageIterator<'_>, Error> {
         Ok(PackageIterator {
             package: self,
-            readdir: walkdir::WalkDir::new(&self.path)
+            readdir: walkdir::WalkDir::new(self.root())
                 .sort_by_file_name()
                 .into_iter(),
             should_keep: |p| !p.is_dir(),
         })
     }
+
+    /// Walks all the files and directories of the package, a directory being
+    /// always returned before its content. The sub-path the package was
+    /// created from is returned as well.
+    pub fn read_entries(&self) -> Result<PackageIterator<'_>, Error> {
+        Ok(PackageIterator {
+            package: self,
+            readdir: walkdir::WalkDir::new(self.root())
+                .min_depth(if self.subpath.is_empty() { 1 } else { 0 })
+                .sort_by_file_name()
+                .into_iter(),
+            should_keep: |_| true,
+        })
+    }
+
+    /// Lists the files and directories placed right below `directory`.
+    pub fn read_children(&self, directory: &str) -> Result<PackageIterator<'_>, Error> {
+        Ok(PackageIterator {
+            package: self,
+            readdir: walkdir::WalkDir::new(self.path.join(directory))
+                .min_depth(1)
+                .max_depth(1)
+                .sort_by_file_name()
+                .into_iter(),
+            should_keep: |_| true,
+        })
+    }
+
+    pub fn is_directory(&self, file: &str) -> bool {
+        std::fs::symlink_metadata(self.path.join(file))
+            .map(|m| m.is_dir())
+            .unwrap_or(false)
+    }
+
+    /// Tells whether `file`, or one of the directories containing it, matches
+    /// a pattern of the copy file of the package.
+    pub fn is_copied(&self, file: &str) -> bool {
+        std::path::Path::new(file)
+            .ancestors()
+            .filter_map(|p| p.to_str())
+            .any(|p| self.copy.is_ignored(p))
+    }
+
+    /// Reads the content of a package file.
+    pub fn read_file(&self, file: &str) -> Result<Vec<u8>, Error> {
+        let path = self.path.join(file);
+        std::fs::read(&path).map_err(|e| {
+            Error::ReadFile(ReadFileError {
+                file: path.display().to_string(),
+                reason: e.to_string(),
+            })
+        })
+    }
+}
+
+/// Lists the packages available in the source directory, which are all its
+/// directories but the ignored ones.
+pub fn list(src_dir: &path::Source, ignore: &ignore::Ignore) -> Result<Vec<String>, Error> {
+    let ignore = ignore::Ignore::builtin().extend(ignore);
+    let read_error = |reason: String| {
+        Error::ReadFile(ReadFileError {
+            file: src_dir.as_ref().display().to_string(),
+            reason,
+        })
+    };
+
+    let mut packages = Vec::new();
+    for entry in std::fs::read_dir(src_dir).map_err(|e| read_error(e.to_string()))? {
+        let entry = entry.map_err(|e| read_error(e.to_string()))?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| read_error(format!("cannot convert path {:?} to string", name)))?;
+        if !ignore.is_ignored(&name) {
+            packages.push(name);
+        }
+    }
+
+    packages.sort();
+    Ok(packages)
+}
+
+/// Splits a package argument such as `zsh/zshrc.mac` into the package name
+/// and the path of the part of the package to use.
+pub fn split(name: &str) -> (&str, &str) {
+    match name.trim_end_matches('/').split_once('/') {
+        Some((name, subpath)) => (name, subpath.trim_start_matches('/')),
+        None => (name.trim_end_matches('/'), ""),
+    }
+}
+
+fn join(name: &str, subpath: &str) -> String {
+    if subpath.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", name, subpath)
+    }
 }
 
 pub struct PackageIterator<'a> {
@@ -42,20 +188,8 @@ pub struct PackageIterator<'a> {
 
 fn entry_to_filepath<'a>(
     package: &'a Package<'a>,
-    entry: walkdir::Result<walkdir::DirEntry>,
-    should_keep: fn(&std::fs::FileType) -> bool,
-) -> Result<Option<String>, Error> {
-    let entry = entry.map_err(|err| {
-        Error::ReadFile(ReadFileError {
-            file: package.name.to_string(),
-            reason: err.to_string(),
-        })
-    })?;
-
-    if !should_keep(&entry.file_type()) {
-        return Ok(None);
-    }
-
+    entry: &walkdir::DirEntry,
+) -> Result<String, Error> {
     entry
         .path()
         .strip_prefix(&package.path)
@@ -77,7 +211,7 @@ fn entry_to_filepath<'a>(
                         reason: format!("cannot convert path {} to string", path.display()),
                     })
                 })
-                .map(|s| Some(s.to_string()))
+                .map(|s| s.to_string())
         })
 }
 
@@ -85,14 +219,31 @@ impl<'a> Iterator for PackageIterator<'a> {
     type Item = Result<String, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut entry = self.readdir.next()?;
         loop {
-            match entry_to_filepath(self.package, entry, self.should_keep) {
-                Ok(None) => {
-                    entry = self.readdir.next()?;
+            let entry = match self.readdir.next()? {
+                Ok(entry) => entry,
+                Err(err) => {
+                    return Some(Err(Error::ReadFile(ReadFileError {
+                        file: self.package.name.to_string(),
+                        reason: err.to_string(),
+                    })))
                 }
-                Ok(Some(file)) => return Some(Ok(file)),
+            };
+
+            let file = match entry_to_filepath(self.package, &entry) {
+                Ok(file) => file,
                 Err(err) => return Some(Err(err)),
+            };
+
+            if self.package.ignore.is_ignored(&file) {
+                if entry.file_type().is_dir() {
+                    self.readdir.skip_current_dir();
+                }
+                continue;
+            }
+
+            if (self.should_keep)(&entry.file_type()) {
+                return Some(Ok(file));
             }
         }
     }
@@ -129,4 +280,134 @@ mod tests {
         assert_eq!("file-1".to_string(), files[0]);
         assert_eq!("file-2".to_string(), files[1]);
     }
+
+    #[test]
+    fn read_entries() {
+        let package =
+            Package::new(&"./golden-files".into(), "package-2").expect("package should exist");
+        let entries: Vec<String> = package
+            .read_entries()
+            .expect("should create a readdir iterator")
+            .collect::<Result<Vec<String>, Error>>()
+            .expect("should collect all entries");
+
+        assert_eq!(
+            vec!["file-1", "subdirectory", "subdirectory/file-2"],
+            entries
+        );
+        assert!(!package.is_directory("file-1"));
+        assert!(package.is_directory("subdirectory"));
+    }
+
+    #[test]
+    fn copied_files() {
+        let package =
+            Package::new(&"./golden-files".into(), "package-7").expect("package should exist");
+
+        assert!(package.is_copied("git/hooks"));
+        assert!(package.is_copied("git/hooks/pre-commit"));
+        assert!(!package.is_copied("git/config.tmpl"));
+        assert!(!package.is_copied("git"));
+    }
+
+    #[test]
+    fn read_entries_subpath() {
+        let testcases = vec![
+            (
+                "package-2/subdirectory",
+                vec!["subdirectory", "subdirectory/file-2"],
+            ),
+            ("package-2/subdirectory/file-2", vec!["subdirectory/file-2"]),
+            ("package-1/file-2/", vec!["file-2"]),
+        ];
+
+        for (name, expected) in testcases {
+            let package =
+                Package::new(&"./golden-files".into(), name).expect("package should exist");
+            let entries: Vec<String> = package
+                .read_entries()
+                .expect("should create a readdir iterator")
+                .collect::<Result<Vec<String>, Error>>()
+                .expect("should collect all entries");
+
+            assert_eq!(name.split('/').next(), Some(package.name()));
+            assert_eq!(expected, entries, "package {}", name);
+        }
+    }
+
+    #[test]
+    fn subpath_not_found() {
+        let err = Package::new(&"./golden-files".into(), "package-1/file-3").unwrap_err();
+        assert_eq!(Error::PackageNotFound("package-1/file-3".to_string()), err)
+    }
+
+    #[test]
+    fn split_names() {
+        let testcases = vec![
+            ("zsh", ("zsh", "")),
+            ("zsh/", ("zsh", "")),
+            ("zsh/zshrc.mac", ("zsh", "zshrc.mac")),
+            ("nvim/.config/nvim/", ("nvim", ".config/nvim")),
+        ];
+
+        for (name, expected) in testcases {
+            assert_eq!(expected, split(name), "name {}", name)
+        }
+    }
+
+    #[test]
+    fn read_children() {
+        let package =
+            Package::new(&"./golden-files".into(), "package-2").expect("package should exist");
+        let children: Vec<String> = package
+            .read_children("")
+            .expect("should create a readdir iterator")
+            .collect::<Result<Vec<String>, Error>>()
+            .expect("should collect all children");
+
+        assert_eq!(vec!["file-1", "subdirectory"], children);
+    }
+
+    #[test]
+    fn read_entries_ignored() {
+        let package =
+            Package::new(&"./golden-files".into(), "package-4").expect("package should exist");
+        let entries: Vec<String> = package
+            .read_entries()
+            .expect("should create a readdir iterator")
+            .collect::<Result<Vec<String>, Error>>()
+            .expect("should collect all entries");
+
+        assert_eq!(
+            vec!["file-4", "subdirectory", "subdirectory/file-4"],
+            entries
+        );
+    }
+
+    #[test]
+    fn read_entries_global_ignore() {
+        let ignore = ignore::Ignore::default()
+            .parse("global", "/subdirectory")
+            .expect("pattern should be valid");
+        let package = Package::new(&"./golden-files".into(), "package-4")
+            .expect("package should exist")
+            .with_ignore(&ignore);
+        let entries: Vec<String> = package
+            .read_entries()
+            .expect("should create a readdir iterator")
+            .collect::<Result<Vec<String>, Error>>()
+            .expect("should collect all entries");
+
+        assert_eq!(vec!["file-4"], entries);
+    }
+
+    #[test]
+    fn list_packages() {
+        let ignore = ignore::Ignore::default()
+            .parse("global", "package-[4-7]")
+            .expect("pattern should be valid");
+        let packages = list(&"./golden-files".into(), &ignore).expect("should list packages");
+
+        assert_eq!(vec!["package-1", "package-2", "package-3"], packages);
+    }
 }
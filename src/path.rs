@@ -72,6 +72,85 @@ impl<'a> Destination<'a> {
     }
 }
 
+/// Lexically resolves the `.` and `..` components of a path, without
+/// touching the filesystem.
+pub fn normalize(path: &std::path::Path) -> std::path::PathBuf {
+    let mut normalized = std::path::PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => match normalized.components().next_back() {
+                Some(std::path::Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(std::path::Component::RootDir) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Computes the path to reach `to` from the directory `from`. Both paths need
+/// to be either absolute or relative to the same directory.
+pub fn relative(from: &std::path::Path, to: &std::path::Path) -> Option<std::path::PathBuf> {
+    if from.is_absolute() != to.is_absolute() {
+        return None;
+    }
+
+    let from = normalize(from);
+    let to = normalize(to);
+    let mut from = from.components().peekable();
+    let mut to = to.components().peekable();
+
+    while let (Some(f), Some(t)) = (from.peek(), to.peek()) {
+        if f != t {
+            break;
+        }
+        from.next();
+        to.next();
+    }
+
+    let mut relative = std::path::PathBuf::new();
+    for component in from {
+        if component == std::path::Component::ParentDir {
+            return None;
+        }
+        relative.push("..");
+    }
+    relative.extend(to);
+
+    Some(relative)
+}
+
+/// Returns the path a symlink located at `link` and pointing to `target`
+/// resolves to, whether the target is absolute or relative to the link.
+pub fn resolve_link(link: &std::path::Path, target: &std::path::Path) -> std::path::PathBuf {
+    match link.parent() {
+        Some(parent) if target.is_relative() => normalize(&parent.join(target)),
+        _ => normalize(target),
+    }
+}
+
+/// Prefix of the package files and directories linked as hidden ones when
+/// using the dotfiles mode.
+pub const DOTFILES_PREFIX: &str = "dot-";
+
+/// Translates a path relative to a package to the name it is linked under in
+/// dotfiles mode, each component starting with `dot-` starting with a `.`
+/// instead.
+pub fn dotfiles(file: &str) -> String {
+    file.split('/')
+        .map(|component| match component.strip_prefix(DOTFILES_PREFIX) {
+            Some(name) if !name.is_empty() => format!(".{}", name),
+            _ => component.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,4 +201,105 @@ mod tests {
         let file = Destination::new(std::path::Path::new("/some/path")).join("to-file");
         assert_eq!(std::path::Path::new("/some/path/to-file"), file)
     }
+
+    #[test]
+    fn normalize_path() {
+        let testcases = vec![
+            ("/home/jdoe/./file", "/home/jdoe/file"),
+            ("/home/jdoe/../file", "/home/file"),
+            ("../dotfiles/./zsh/../vim", "../dotfiles/vim"),
+            ("/..", "/"),
+            ("../../dotfiles", "../../dotfiles"),
+        ];
+
+        for (path, expected) in testcases {
+            assert_eq!(
+                std::path::Path::new(expected),
+                normalize(std::path::Path::new(path)),
+                "cannot normalize {}",
+                path
+            )
+        }
+    }
+
+    #[test]
+    fn relative_path() {
+        let testcases = vec![
+            (
+                "/home/jdoe",
+                "/home/jdoe/dotfiles/zsh/zshrc",
+                Some("dotfiles/zsh/zshrc"),
+            ),
+            (
+                "/home/jdoe/.config/nvim",
+                "/home/jdoe/dotfiles/nvim/init.lua",
+                Some("../../dotfiles/nvim/init.lua"),
+            ),
+            (
+                "/home/jdoe",
+                "/opt/dotfiles/zshrc",
+                Some("../../opt/dotfiles/zshrc"),
+            ),
+            ("home", "dotfiles/zshrc", Some("../dotfiles/zshrc")),
+            ("/home/jdoe", "dotfiles/zshrc", None),
+            ("../home", "dotfiles/zshrc", None),
+        ];
+
+        for (from, to, expected) in testcases {
+            assert_eq!(
+                expected.map(std::path::PathBuf::from),
+                relative(std::path::Path::new(from), std::path::Path::new(to)),
+                "cannot compute path from {} to {}",
+                from,
+                to
+            )
+        }
+    }
+
+    #[test]
+    fn resolve_link_target() {
+        let testcases = vec![
+            (
+                "/home/jdoe/.zshrc",
+                "dotfiles/zsh/zshrc",
+                "/home/jdoe/dotfiles/zsh/zshrc",
+            ),
+            (
+                "/home/jdoe/.config/nvim",
+                "../dotfiles/nvim",
+                "/home/jdoe/dotfiles/nvim",
+            ),
+            (
+                "/home/jdoe/.zshrc",
+                "/opt/dotfiles/zshrc",
+                "/opt/dotfiles/zshrc",
+            ),
+        ];
+
+        for (link, target, expected) in testcases {
+            assert_eq!(
+                std::path::Path::new(expected),
+                resolve_link(std::path::Path::new(link), std::path::Path::new(target)),
+                "cannot resolve link {} to {}",
+                link,
+                target
+            )
+        }
+    }
+
+    #[test]
+    fn dotfiles_path() {
+        let testcases = vec![
+            ("dot-zshrc", ".zshrc"),
+            ("dot-config/nvim/init.lua", ".config/nvim/init.lua"),
+            ("dot-config/nvim/dot-luarc.json", ".config/nvim/.luarc.json"),
+            ("config/a-dot-file", "config/a-dot-file"),
+            ("dot-", "dot-"),
+            ("", ""),
+        ];
+
+        for (path, expected) in testcases {
+            assert_eq!(expected, dotfiles(path), "cannot translate {}", path)
+        }
+    }
 }
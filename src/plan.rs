@@ -0,0 +1,437 @@
+use crate::{linker, Error};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operation {
+    CreateDirectory(std::path::PathBuf),
+    CreateSymlink {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+    },
+    CreateHardlink {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+    },
+    DeleteFile(std::path::PathBuf),
+    RemoveDirectory(std::path::PathBuf),
+    Rename {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+    },
+    /// Moves the file `source`, standing where a link is about to be created,
+    /// aside to `destination`.
+    Backup {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+    },
+    /// Writes the rendering of the `source` template to `destination`.
+    WriteFile {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+        content: String,
+    },
+    /// Copies `source` to `destination`, `checksum` being the one of the
+    /// content of `source`.
+    CopyFile {
+        source: std::path::PathBuf,
+        destination: std::path::PathBuf,
+        checksum: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Action {
+    pub package: String,
+    pub operation: Operation,
+}
+
+impl Action {
+    pub fn execute<L: linker::Linker + ?Sized>(&self, linker: &mut L) -> Result<(), Error> {
+        match &self.operation {
+            Operation::CreateDirectory(directory) => linker.create_directory(directory),
+            Operation::CreateSymlink {
+                source,
+                destination,
+            } => linker.create_symlink(&source.as_path().into(), &destination.as_path().into()),
+            Operation::CreateHardlink {
+                source,
+                destination,
+            } => linker.create_hardlink(source, destination),
+            Operation::DeleteFile(file) => linker.delete_file(file),
+            Operation::RemoveDirectory(directory) => linker.remove_directory(directory),
+            Operation::Rename {
+                source,
+                destination,
+            }
+            | Operation::Backup {
+                source,
+                destination,
+            } => linker.rename(source, destination),
+            Operation::WriteFile {
+                destination,
+                content,
+                ..
+            } => linker.write_file(destination, content.as_bytes()),
+            Operation::CopyFile {
+                source,
+                destination,
+                ..
+            } => linker.copy_file(source, destination),
+        }
+    }
+}
+
+/// How a file standing where a link should be created is handled.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum ConflictPolicy {
+    #[default]
+    Abort,
+    Skip,
+    Overwrite,
+    Backup,
+    Adopt,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "backup" => Ok(Self::Backup),
+            "adopt" => Ok(Self::Adopt),
+            _ => Err(Error::Generic(format!(
+                "unknown conflict policy {} (expected abort, skip, overwrite, backup or adopt)",
+                s
+            ))),
+        }
+    }
+}
+
+impl TryFrom<String> for ConflictPolicy {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Abort => write!(f, "aborted"),
+            Self::Skip => write!(f, "skipped"),
+            Self::Overwrite => write!(f, "overwritten"),
+            Self::Backup => write!(f, "backed up"),
+            Self::Adopt => write!(f, "adopted"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Conflict {
+    pub package: String,
+    pub destination: std::path::PathBuf,
+    pub reason: String,
+    pub resolution: ConflictPolicy,
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}, {}",
+            self.destination.display(),
+            self.package,
+            self.reason,
+            self.resolution
+        )
+    }
+}
+
+/// What the destination tree looks like at a given path, once all the
+/// actions already planned are taken into account.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Entry {
+    Missing,
+    Directory,
+    File,
+    Symlink(std::path::PathBuf),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    pub conflicts: Vec<Conflict>,
+    /// Destinations of the recorded links which don't exist anymore, dropped
+    /// from the state when the plan is executed.
+    pub forgotten: Vec<std::path::PathBuf>,
+}
+
+impl Plan {
+    pub fn push(&mut self, package: &str, operation: Operation) {
+        self.actions.push(Action {
+            package: package.to_string(),
+            operation,
+        })
+    }
+
+    pub fn conflict(
+        &mut self,
+        package: &str,
+        destination: &std::path::Path,
+        reason: String,
+        resolution: ConflictPolicy,
+    ) {
+        self.conflicts.push(Conflict {
+            package: package.to_string(),
+            destination: destination.to_path_buf(),
+            reason,
+            resolution,
+        })
+    }
+
+    pub fn forget(&mut self, destination: &std::path::Path) {
+        self.forgotten.push(destination.to_path_buf())
+    }
+
+    /// Counts the conflicts which prevent the plan from being executed.
+    pub fn blocking_conflicts(&self) -> usize {
+        self.conflicts
+            .iter()
+            .filter(|c| c.resolution == ConflictPolicy::Abort)
+            .count()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.blocking_conflicts() == 0
+    }
+
+    /// Returns the state of `path` as left by the planned actions, or `None`
+    /// when no planned action touches it.
+    pub fn entry(&self, path: &std::path::Path) -> Option<Entry> {
+        self.actions
+            .iter()
+            .rev()
+            .find_map(|action| match &action.operation {
+                Operation::CreateDirectory(directory) if directory.starts_with(path) => {
+                    Some(Entry::Directory)
+                }
+                Operation::CreateSymlink {
+                    source,
+                    destination,
+                } if destination == path => Some(Entry::Symlink(source.clone())),
+                Operation::DeleteFile(file) if path.starts_with(file) => Some(Entry::Missing),
+                Operation::RemoveDirectory(directory) if path.starts_with(directory) => {
+                    Some(Entry::Missing)
+                }
+                Operation::Rename { source, .. } | Operation::Backup { source, .. }
+                    if source == path =>
+                {
+                    Some(Entry::Missing)
+                }
+                Operation::Rename { destination, .. } | Operation::Backup { destination, .. }
+                    if destination == path =>
+                {
+                    Some(Entry::File)
+                }
+                Operation::CreateHardlink { destination, .. }
+                | Operation::WriteFile { destination, .. }
+                | Operation::CopyFile { destination, .. }
+                    if destination == path =>
+                {
+                    Some(Entry::File)
+                }
+                _ => None,
+            })
+    }
+
+    pub fn execute<L: linker::Linker + ?Sized>(&self, linker: &mut L) -> Result<(), Error> {
+        self.execute_with(linker, |_| {})
+    }
+
+    /// Executes the plan, calling `executed` after each action successfully
+    /// executed.
+    pub fn execute_with<L, F>(&self, linker: &mut L, mut executed: F) -> Result<(), Error>
+    where
+        L: linker::Linker + ?Sized,
+        F: FnMut(&Action),
+    {
+        if !self.is_valid() {
+            return Err(Error::Conflicts(self.blocking_conflicts()));
+        }
+
+        for action in self.actions.iter() {
+            action.execute(linker)?;
+            executed(action);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conflict_policy() {
+        let testcases = vec![
+            ("abort", ConflictPolicy::Abort),
+            ("skip", ConflictPolicy::Skip),
+            ("overwrite", ConflictPolicy::Overwrite),
+            ("backup", ConflictPolicy::Backup),
+            ("adopt", ConflictPolicy::Adopt),
+        ];
+
+        for (value, expected) in testcases {
+            assert_eq!(Ok(expected), value.parse::<ConflictPolicy>())
+        }
+    }
+
+    #[test]
+    fn parse_invalid_conflict_policy() {
+        assert_eq!(
+            Err(Error::Generic(
+                "unknown conflict policy delete (expected abort, skip, overwrite, backup or adopt)"
+                    .to_string()
+            )),
+            "delete".parse::<ConflictPolicy>()
+        )
+    }
+
+    #[test]
+    fn display_conflict() {
+        let conflict = Conflict {
+            package: "git".to_string(),
+            destination: "/home/jdoe/.gitconfig".into(),
+            reason: "existing file".to_string(),
+            resolution: ConflictPolicy::Skip,
+        };
+
+        assert_eq!(
+            "/home/jdoe/.gitconfig (git): existing file, skipped",
+            format!("{}", conflict)
+        )
+    }
+
+    #[test]
+    fn entry_not_planned() {
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe".into()));
+
+        assert_eq!(None, plan.entry("/home/jdoe/file".as_ref()))
+    }
+
+    #[test]
+    fn entry_parent_of_planned_directory() {
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe/a/b".into()));
+
+        assert_eq!(Some(Entry::Directory), plan.entry("/home/jdoe/a".as_ref()))
+    }
+
+    #[test]
+    fn entry_last_action_wins() {
+        let mut plan = Plan::default();
+        plan.push(
+            "pkg",
+            Operation::CreateSymlink {
+                source: "/src/pkg/file".into(),
+                destination: "/home/jdoe/file".into(),
+            },
+        );
+        assert_eq!(
+            Some(Entry::Symlink("/src/pkg/file".into())),
+            plan.entry("/home/jdoe/file".as_ref())
+        );
+
+        plan.push("pkg", Operation::DeleteFile("/home/jdoe/file".into()));
+        assert_eq!(Some(Entry::Missing), plan.entry("/home/jdoe/file".as_ref()));
+    }
+
+    #[test]
+    fn entry_removed_directory() {
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::RemoveDirectory("/home/jdoe/a".into()));
+
+        assert_eq!(Some(Entry::Missing), plan.entry("/home/jdoe/a".as_ref()));
+        assert_eq!(Some(Entry::Missing), plan.entry("/home/jdoe/a/b".as_ref()));
+        assert_eq!(None, plan.entry("/home/jdoe".as_ref()));
+    }
+
+    #[test]
+    fn entry_unfolded_directory() {
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::DeleteFile("/home/jdoe/a".into()));
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe/a".into()));
+
+        assert_eq!(Some(Entry::Directory), plan.entry("/home/jdoe/a".as_ref()));
+        assert_eq!(Some(Entry::Missing), plan.entry("/home/jdoe/a/b".as_ref()));
+    }
+
+    #[test]
+    fn execute_plan() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Verbose::new(&mut output, linker::Noop::default());
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe".into()));
+        plan.push(
+            "pkg",
+            Operation::CreateSymlink {
+                source: "/src/pkg/file".into(),
+                destination: "/home/jdoe/file".into(),
+            },
+        );
+        plan.push("pkg", Operation::DeleteFile("/home/jdoe/file".into()));
+
+        plan.execute(&mut linker).expect("cannot execute plan");
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            "mkdir -p /home/jdoe\nln -s /src/pkg/file /home/jdoe/file\nrm /home/jdoe/file\n",
+            content
+        )
+    }
+
+    #[test]
+    fn execute_plan_with_resolved_conflicts() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Verbose::new(&mut output, linker::Noop::default());
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe".into()));
+        plan.conflict(
+            "pkg",
+            "/home/jdoe/file".as_ref(),
+            "existing file".to_string(),
+            ConflictPolicy::Skip,
+        );
+
+        plan.execute(&mut linker).expect("cannot execute plan");
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        assert_eq!("mkdir -p /home/jdoe\n", content)
+    }
+
+    #[test]
+    fn execute_plan_with_conflicts() {
+        let mut output = std::io::BufWriter::new(Vec::new());
+        let mut linker = linker::Verbose::new(&mut output, linker::Noop::default());
+        let mut plan = Plan::default();
+        plan.push("pkg", Operation::CreateDirectory("/home/jdoe".into()));
+        plan.conflict(
+            "pkg",
+            "/home/jdoe/file".as_ref(),
+            "existing directory".to_string(),
+            ConflictPolicy::Abort,
+        );
+
+        let err = plan.execute(&mut linker).unwrap_err();
+
+        let content = String::from_utf8(output.into_inner().unwrap()).unwrap();
+        assert_eq!(Error::Conflicts(1), err);
+        assert_eq!("", content)
+    }
+}
@@ -0,0 +1,409 @@
+use sha2::Digest;
+
+use crate::{plan, Error, ReadFileError};
+
+/// Name of the file, in the stow directory, recording the links created by
+/// stow.
+pub const STATE_FILE: &str = ".stow-state";
+
+/// Symlink, hardlink, or copied or rendered file, created by stow for a
+/// package.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Link {
+    pub package: String,
+    pub destination: std::path::PathBuf,
+    /// Target of the symlink, as written in it, or package file the other
+    /// kinds of files were created from.
+    pub source: std::path::PathBuf,
+    /// Checksum of the content written, for files which are not symlinks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hardlink: bool,
+    /// Location of the file moved aside to make room for the link, restored
+    /// when unstowing with --restore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<std::path::PathBuf>,
+}
+
+/// Profile applied to a target directory, with the packages it was made of
+/// at the time.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    pub target: std::path::PathBuf,
+    pub name: String,
+    pub packages: Vec<String>,
+}
+
+/// Record of the links created by stow, so they can be removed once the
+/// package they belong to doesn't exist anymore, and of the profiles applied
+/// to each target directory.
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    #[serde(default)]
+    links: Vec<Link>,
+    #[serde(default)]
+    profiles: Vec<Profile>,
+    #[serde(skip)]
+    modified: bool,
+    /// Backups made for links which are not created yet, or whose link has
+    /// just been removed and may be created again, as (destination, backup)
+    /// pairs.
+    #[serde(skip)]
+    backups: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+}
+
+impl State {
+    /// Reads the state stored in `file`, starting from an empty state when the
+    /// file doesn't exist yet.
+    pub fn load(file: &std::path::Path) -> Result<Self, Error> {
+        let read_error = |reason: String| {
+            Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason,
+            })
+        };
+
+        match std::fs::read_to_string(file) {
+            Ok(content) => toml::from_str(&content).map_err(|e| read_error(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(read_error(e.to_string())),
+        }
+    }
+
+    /// Writes the state to `file` when it changed since it was loaded.
+    pub fn save(&self, file: &std::path::Path) -> Result<(), Error> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let content = toml::to_string(self)
+            .map_err(|e| Error::Generic(format!("cannot serialize state: {}", e)))?;
+        std::fs::write(file, content)
+            .map_err(|e| Error::Generic(format!("cannot write state {}: {}", file.display(), e)))
+    }
+
+    /// Lists the links recorded for `package` below `directory`.
+    pub fn links(&self, package: &str, directory: &std::path::Path) -> Vec<Link> {
+        self.links
+            .iter()
+            .filter(|l| l.package == package && l.destination.starts_with(directory))
+            .cloned()
+            .collect()
+    }
+
+    /// Finds the record of the link created at `destination`.
+    pub fn link(&self, destination: &std::path::Path) -> Option<&Link> {
+        self.links.iter().find(|l| l.destination == destination)
+    }
+
+    /// Drops the record of a link which doesn't exist anymore.
+    pub fn forget(&mut self, destination: &std::path::Path) {
+        let count = self.links.len();
+        self.links.retain(|l| l.destination != destination);
+        self.modified |= count != self.links.len();
+    }
+
+    /// Tells which profile is applied to the `target` directory.
+    pub fn profile(&self, target: &std::path::Path) -> Option<&Profile> {
+        self.profiles.iter().find(|p| p.target == target)
+    }
+
+    /// Records that the profile `name`, made of `packages`, is applied to
+    /// the `target` directory.
+    pub fn apply(&mut self, target: &std::path::Path, name: &str, packages: &[String]) {
+        self.forget_profile(target);
+        self.profiles.push(Profile {
+            target: target.to_path_buf(),
+            name: name.to_string(),
+            packages: packages.to_vec(),
+        });
+        self.modified = true;
+    }
+
+    /// Drops the record of the profile applied to the `target` directory.
+    pub fn forget_profile(&mut self, target: &std::path::Path) {
+        let count = self.profiles.len();
+        self.profiles.retain(|p| p.target != target);
+        self.modified |= count != self.profiles.len();
+    }
+
+    /// Updates the state with an action which has been executed.
+    pub fn record(&mut self, action: &plan::Action) {
+        let (source, destination, checksum, hardlink) = match &action.operation {
+            plan::Operation::CreateSymlink {
+                source,
+                destination,
+            } => (source, destination, None, false),
+            plan::Operation::CreateHardlink {
+                source,
+                destination,
+            } => (source, destination, None, true),
+            plan::Operation::WriteFile {
+                source,
+                destination,
+                content,
+            } => (
+                source,
+                destination,
+                Some(checksum(content.as_bytes())),
+                false,
+            ),
+            plan::Operation::CopyFile {
+                source,
+                destination,
+                checksum,
+            } => (source, destination, Some(checksum.clone()), false),
+            plan::Operation::Backup {
+                source,
+                destination,
+            } => return self.backups.push((source.clone(), destination.clone())),
+            plan::Operation::DeleteFile(file) => {
+                if let Some(backup) = self.link(file).and_then(|l| l.backup.clone()) {
+                    self.backups.push((file.clone(), backup));
+                }
+                return self.forget(file);
+            }
+            plan::Operation::Rename { source, .. } => {
+                return self.backups.retain(|(_, backup)| backup != source)
+            }
+            plan::Operation::CreateDirectory(_) | plan::Operation::RemoveDirectory(_) => return,
+        };
+
+        let backup = self
+            .backups
+            .iter()
+            .position(|(file, _)| file == destination)
+            .map(|i| self.backups.remove(i).1);
+        self.links.retain(|l| &l.destination != destination);
+        self.links.push(Link {
+            package: action.package.clone(),
+            destination: destination.clone(),
+            source: source.clone(),
+            checksum,
+            hardlink,
+            backup,
+        });
+        self.modified = true;
+    }
+}
+
+/// Computes the checksum recorded for the content of a file.
+pub fn checksum(content: &[u8]) -> String {
+    format!("{:x}", sha2::Sha256::digest(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(package: &str, destination: &str, source: &str) -> Link {
+        Link {
+            package: package.to_string(),
+            destination: destination.into(),
+            source: source.into(),
+            checksum: None,
+            hardlink: false,
+            backup: None,
+        }
+    }
+
+    #[test]
+    fn record_actions() {
+        let mut state = State::default();
+        let mut plan = plan::Plan::default();
+        plan.push(
+            "zsh",
+            plan::Operation::CreateSymlink {
+                source: "dotfiles/zsh/zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        );
+        plan.push(
+            "zsh",
+            plan::Operation::CreateSymlink {
+                source: "dotfiles/zsh/zshenv".into(),
+                destination: "/home/jdoe/.zshenv".into(),
+            },
+        );
+        plan.push(
+            "zsh",
+            plan::Operation::DeleteFile("/home/jdoe/.zshenv".into()),
+        );
+        plan.push(
+            "vim",
+            plan::Operation::CreateSymlink {
+                source: "dotfiles/vim/vimrc".into(),
+                destination: "/opt/jdoe/.vimrc".into(),
+            },
+        );
+
+        for action in plan.actions.iter() {
+            state.record(action);
+        }
+
+        assert_eq!(
+            vec![link("zsh", "/home/jdoe/.zshrc", "dotfiles/zsh/zshrc")],
+            state.links("zsh", "/home/jdoe".as_ref())
+        );
+        assert_eq!(
+            Vec::<Link>::new(),
+            state.links("vim", "/home/jdoe".as_ref())
+        );
+    }
+
+    #[test]
+    fn record_backups() {
+        let mut state = State::default();
+        let mut plan = plan::Plan::default();
+        plan.push(
+            "zsh",
+            plan::Operation::Backup {
+                source: "/home/jdoe/.zshrc".into(),
+                destination: "/home/jdoe/.stow-backup/zsh/.zshrc".into(),
+            },
+        );
+        plan.push(
+            "zsh",
+            plan::Operation::CreateSymlink {
+                source: "dotfiles/zsh/zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        );
+        // restowing keeps the backup
+        plan.push(
+            "zsh",
+            plan::Operation::DeleteFile("/home/jdoe/.zshrc".into()),
+        );
+        plan.push(
+            "zsh",
+            plan::Operation::CreateSymlink {
+                source: "dotfiles/zsh/.zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        );
+        for action in plan.actions.iter() {
+            state.record(action);
+        }
+
+        assert_eq!(
+            vec![Link {
+                backup: Some("/home/jdoe/.stow-backup/zsh/.zshrc".into()),
+                ..link("zsh", "/home/jdoe/.zshrc", "dotfiles/zsh/.zshrc")
+            }],
+            state.links("zsh", "/home/jdoe".as_ref())
+        );
+
+        state.record(&plan::Action {
+            package: "zsh".to_string(),
+            operation: plan::Operation::DeleteFile("/home/jdoe/.zshrc".into()),
+        });
+        state.record(&plan::Action {
+            package: "zsh".to_string(),
+            operation: plan::Operation::Rename {
+                source: "/home/jdoe/.stow-backup/zsh/.zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        });
+        assert_eq!(State::default().backups, state.backups);
+    }
+
+    #[test]
+    fn record_written_files() {
+        let mut state = State::default();
+        state.record(&plan::Action {
+            package: "git".to_string(),
+            operation: plan::Operation::WriteFile {
+                source: "/home/jdoe/dotfiles/git/gitconfig.tmpl".into(),
+                destination: "/home/jdoe/.gitconfig".into(),
+                content: "[user]\n".to_string(),
+            },
+        });
+        state.record(&plan::Action {
+            package: "git".to_string(),
+            operation: plan::Operation::CopyFile {
+                source: "/home/jdoe/dotfiles/git/gitignore".into(),
+                destination: "/home/jdoe/.gitignore".into(),
+                checksum: "a1b2c3".to_string(),
+            },
+        });
+
+        assert_eq!(
+            vec![
+                Link {
+                    checksum: Some(
+                        "37411c06650b34746ff1b60a9bb4148608d868972b658eb56bbacea8f504f7b2"
+                            .to_string()
+                    ),
+                    ..link(
+                        "git",
+                        "/home/jdoe/.gitconfig",
+                        "/home/jdoe/dotfiles/git/gitconfig.tmpl"
+                    )
+                },
+                Link {
+                    checksum: Some("a1b2c3".to_string()),
+                    ..link(
+                        "git",
+                        "/home/jdoe/.gitignore",
+                        "/home/jdoe/dotfiles/git/gitignore"
+                    )
+                },
+            ],
+            state.links("git", "/home/jdoe".as_ref())
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let file = std::env::temp_dir().join(format!("stow-state-{}", uuid::Uuid::new_v4()));
+        let mut state = State::default();
+        state.record(&plan::Action {
+            package: "zsh".to_string(),
+            operation: plan::Operation::CreateSymlink {
+                source: "dotfiles/zsh/zshrc".into(),
+                destination: "/home/jdoe/.zshrc".into(),
+            },
+        });
+
+        state.save(&file).expect("cannot save state");
+        let loaded = State::load(&file).expect("cannot load state");
+        std::fs::remove_file(&file).expect("cannot remove state");
+
+        assert_eq!(
+            vec![link("zsh", "/home/jdoe/.zshrc", "dotfiles/zsh/zshrc")],
+            loaded.links("zsh", "/home/jdoe".as_ref())
+        );
+    }
+
+    #[test]
+    fn apply_profiles() {
+        let mut state = State::default();
+        state.apply(
+            "/home/jdoe".as_ref(),
+            "work-mac",
+            &["zsh".to_string(), "git".to_string()],
+        );
+        state.apply("/opt/jdoe".as_ref(), "server", &["vim".to_string()]);
+        state.apply("/home/jdoe".as_ref(), "linux-vm", &["zsh".to_string()]);
+
+        assert_eq!(
+            Some(&Profile {
+                target: "/home/jdoe".into(),
+                name: "linux-vm".to_string(),
+                packages: vec!["zsh".to_string()],
+            }),
+            state.profile("/home/jdoe".as_ref())
+        );
+
+        state.forget_profile("/opt/jdoe".as_ref());
+        assert_eq!(None, state.profile("/opt/jdoe".as_ref()));
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let state = State::load("/not/a/file".as_ref()).expect("missing file should be skipped");
+
+        assert_eq!(State::default(), state);
+    }
+}
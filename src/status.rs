@@ -0,0 +1,160 @@
+/// Health of the link of a package file in the target directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum State {
+    /// The file is linked, directly or through one of its directories.
+    Linked,
+    Missing,
+    /// A symlink stands in place of the file but targets another file.
+    Elsewhere(std::path::PathBuf),
+    /// A regular file or a directory stands in place of the file.
+    Blocked,
+    /// A symlink stands in place of the file but its target doesn't exist.
+    Broken(std::path::PathBuf),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    pub package: String,
+    pub destination: std::path::PathBuf,
+    pub state: State,
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): ", self.destination.display(), self.package)?;
+        match &self.state {
+            State::Linked => write!(f, "linked"),
+            State::Missing => write!(f, "missing"),
+            State::Elsewhere(target) => write!(f, "links to {}", target.display()),
+            State::Blocked => write!(f, "blocked by an existing file"),
+            State::Broken(target) => write!(f, "broken link to {}", target.display()),
+        }
+    }
+}
+
+/// State of all the files of a set of packages, displayed as a table
+/// counting the files in each state per package, followed by the files which
+/// are not linked.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub packages: Vec<String>,
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    fn count(&self, package: &str, state: fn(&State) -> bool) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.package == package && state(&e.state))
+            .count()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .packages
+            .iter()
+            .map(|p| p.len())
+            .chain(std::iter::once("PACKAGE".len()))
+            .max()
+            .unwrap_or_default();
+
+        writeln!(
+            f,
+            "{:<width$}  LINKED  MISSING  ELSEWHERE  BLOCKED  BROKEN",
+            "PACKAGE"
+        )?;
+        for p in self.packages.iter() {
+            writeln!(
+                f,
+                "{:<width$}  {:<6}  {:<7}  {:<9}  {:<7}  {}",
+                p,
+                self.count(p, |s| matches!(s, State::Linked)),
+                self.count(p, |s| matches!(s, State::Missing)),
+                self.count(p, |s| matches!(s, State::Elsewhere(_))),
+                self.count(p, |s| matches!(s, State::Blocked)),
+                self.count(p, |s| matches!(s, State::Broken(_))),
+            )?;
+        }
+
+        let unlinked = self.entries.iter().filter(|e| e.state != State::Linked);
+        for (i, entry) in unlinked.enumerate() {
+            if i == 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_entry() {
+        let testcases = vec![
+            (State::Linked, "/home/jdoe/.zshrc (zsh): linked"),
+            (State::Missing, "/home/jdoe/.zshrc (zsh): missing"),
+            (
+                State::Elsewhere("/opt/zshrc".into()),
+                "/home/jdoe/.zshrc (zsh): links to /opt/zshrc",
+            ),
+            (
+                State::Blocked,
+                "/home/jdoe/.zshrc (zsh): blocked by an existing file",
+            ),
+            (
+                State::Broken("/opt/zshrc".into()),
+                "/home/jdoe/.zshrc (zsh): broken link to /opt/zshrc",
+            ),
+        ];
+
+        for (state, expected) in testcases {
+            let entry = Entry {
+                package: "zsh".to_string(),
+                destination: "/home/jdoe/.zshrc".into(),
+                state,
+            };
+            assert_eq!(expected, format!("{}", entry))
+        }
+    }
+
+    #[test]
+    fn display_report() {
+        let report = Report {
+            packages: vec!["zsh".to_string(), "neovim".to_string()],
+            entries: vec![
+                Entry {
+                    package: "zsh".to_string(),
+                    destination: "/home/jdoe/.zshrc".into(),
+                    state: State::Linked,
+                },
+                Entry {
+                    package: "zsh".to_string(),
+                    destination: "/home/jdoe/.zshenv".into(),
+                    state: State::Missing,
+                },
+                Entry {
+                    package: "neovim".to_string(),
+                    destination: "/home/jdoe/.config/nvim".into(),
+                    state: State::Blocked,
+                },
+            ],
+        };
+
+        assert_eq!(
+            "PACKAGE  LINKED  MISSING  ELSEWHERE  BLOCKED  BROKEN
+zsh      1       1        0          0        0
+neovim   0       0        0          1        0
+
+/home/jdoe/.zshenv (zsh): missing
+/home/jdoe/.config/nvim (neovim): blocked by an existing file
+",
+            format!("{}", report)
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{variant, Error, ReadFileError};
+
+/// Suffix of the package files rendered in the target directory instead of
+/// being linked, e.g. `gitconfig.tmpl` rendered as `gitconfig`.
+pub const SUFFIX: &str = ".tmpl";
+
+/// Path, relative to the home directory, of the file defining the variables
+/// available in templates, as a TOML table of strings.
+pub const VARIABLES_FILE: &str = ".config/stow/vars.toml";
+
+/// Values substituted to the `{{ name }}` placeholders of the templates.
+///
+/// The `host`, `os` and `user` variables describe the machine, and any other
+/// variable comes from the variables file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Variables {
+    values: std::collections::BTreeMap<String, String>,
+}
+
+impl Variables {
+    /// Creates the variables describing the machine.
+    pub fn new(machine: &variant::Machine) -> Self {
+        Self::default()
+            .with("host", &machine.host)
+            .with("os", &machine.os)
+            .with("user", &machine.user)
+    }
+
+    pub fn with(mut self, name: &str, value: &str) -> Self {
+        self.values.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds the variables defined in `file`, if it exists.
+    pub fn load(mut self, file: &std::path::Path) -> Result<Self, Error> {
+        let read_error = |reason: String| {
+            Error::ReadFile(ReadFileError {
+                file: file.display().to_string(),
+                reason,
+            })
+        };
+
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(self),
+            Err(e) => return Err(read_error(e.to_string())),
+        };
+
+        let values: std::collections::BTreeMap<String, String> =
+            toml::from_str(&content).map_err(|e| read_error(e.to_string()))?;
+        self.values.extend(values);
+
+        Ok(self)
+    }
+
+    /// Replaces the placeholders of the `template` read from `file`.
+    pub fn render(&self, file: &str, template: &str) -> Result<String, Error> {
+        let render_error = |reason: String| {
+            Error::ReadFile(ReadFileError {
+                file: file.to_string(),
+                reason,
+            })
+        };
+
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            rendered.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| render_error("unclosed placeholder".to_string()))?;
+            let name = rest[start + 2..start + end].trim();
+            let value = self
+                .values
+                .get(name)
+                .ok_or_else(|| render_error(format!("unknown variable {}", name)))?;
+            rendered.push_str(value);
+            rest = &rest[start + end + 2..];
+        }
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+}
+
+/// Tells whether a package file is a template.
+pub fn is_template(file: &str) -> bool {
+    file.len() > SUFFIX.len() && file.ends_with(SUFFIX)
+}
+
+/// Name of a file once rendered, without the template suffix.
+pub fn rendered(file: &str) -> &str {
+    if is_template(file) {
+        &file[..file.len() - SUFFIX.len()]
+    } else {
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template() {
+        let variables = Variables::new(&variant::Machine {
+            os: "linux".to_string(),
+            host: "work-vm".to_string(),
+            user: "jdoe".to_string(),
+            tags: vec![],
+        })
+        .with("email", "jdoe@example.com");
+
+        assert_eq!(
+            Ok("[user]\nname = jdoe\nemail = jdoe@example.com # work-vm\n".to_string()),
+            variables.render(
+                "gitconfig.tmpl",
+                "[user]\nname = {{user}}\nemail = {{ email }} # {{ host }}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn render_invalid_template() {
+        let variables = Variables::default().with("user", "jdoe");
+
+        let testcases = vec![
+            ("{{ email }}", "unknown variable email"),
+            ("name = {{ user", "unclosed placeholder"),
+        ];
+
+        for (template, reason) in testcases {
+            assert_eq!(
+                Err(Error::ReadFile(ReadFileError {
+                    file: "gitconfig.tmpl".to_string(),
+                    reason: reason.to_string(),
+                })),
+                variables.render("gitconfig.tmpl", template)
+            )
+        }
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let variables = Variables::default()
+            .load("/not/a/file".as_ref())
+            .expect("missing file should be skipped");
+
+        assert_eq!(Variables::default(), variables);
+    }
+
+    #[test]
+    fn rendered_name() {
+        let testcases = vec![
+            ("gitconfig.tmpl", "gitconfig"),
+            ("config/git/config.tmpl", "config/git/config"),
+            ("gitconfig", "gitconfig"),
+            (".tmpl", ".tmpl"),
+        ];
+
+        for (file, expected) in testcases {
+            assert_eq!(expected, rendered(file), "file {}", file)
+        }
+    }
+}
@@ -0,0 +1,236 @@
+/// Separator between the name of a file and the conditions on which it is
+/// used, as in `gitconfig##host.work-vm`.
+pub const SEPARATOR: &str = "##";
+
+/// Condition matching any machine, used when no other variant matches.
+const DEFAULT_CONDITION: &str = "default";
+
+/// Description of the machine the packages are stowed on, telling which
+/// variant of a file to use.
+///
+/// A variant is a file named after the file it stands for, followed by `##`
+/// and a comma separated list of conditions, which must all match:
+/// `os.<name>`, `host.<name>`, `user.<name>` or `tag.<name>`. The `default`
+/// condition always matches. When several variants of a file match, the most
+/// specific wins: a host is more specific than a user, then a tag, then an OS.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub os: String,
+    pub host: String,
+    pub user: String,
+    pub tags: Vec<String>,
+}
+
+impl Machine {
+    /// Describes the machine running the program.
+    pub fn current() -> Self {
+        let host = std::process::Command::new("hostname")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_default();
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_default();
+
+        Self {
+            os: std::env::consts::OS.to_string(),
+            host: host.trim().to_string(),
+            user,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Adds custom tags matched by the `tag.<name>` conditions.
+    pub fn with_tags(mut self, tags: &[String]) -> Self {
+        self.tags.extend(tags.iter().cloned());
+        self
+    }
+
+    /// Scores how specific a variant is for this machine, or returns `None`
+    /// when one of its conditions doesn't match.
+    fn score(&self, conditions: &str) -> Option<usize> {
+        let mut score = 0;
+        for condition in conditions.split(',').map(str::trim) {
+            if condition == DEFAULT_CONDITION {
+                continue;
+            }
+
+            let (kind, value) = condition.split_once('.')?;
+            score += match kind {
+                "host" if value == self.host || Some(value) == self.host.split('.').next() => 8,
+                "user" if value == self.user => 4,
+                "tag" if self.tags.iter().any(|t| t == value) => 2,
+                "os" if value == self.os => 1,
+                _ => return None,
+            };
+        }
+
+        Some(score)
+    }
+}
+
+/// Path of a package file once the conditions of each of its components are
+/// removed, which is where it is placed in the target directory.
+pub fn base(file: &str) -> String {
+    if !file.contains(SEPARATOR) {
+        return file.to_string();
+    }
+
+    file.split('/')
+        .map(|component| split(component).0)
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+/// Keeps, among package files, only the variants to use on the machine. A
+/// file without conditions is kept unless another of its variants matches,
+/// and the content of a discarded directory is discarded as well.
+pub fn select(entries: Vec<String>, machine: &Machine) -> Vec<String> {
+    let known: std::collections::HashSet<&str> = entries.iter().map(String::as_str).collect();
+    let mut best: std::collections::HashMap<(&str, &str), (usize, &str)> =
+        std::collections::HashMap::new();
+
+    for entry in entries.iter() {
+        let (parent, name) = split_parent(entry);
+        let (base, conditions) = split(name);
+        let score = match conditions {
+            Some(conditions) => machine.score(conditions),
+            None => Some(0),
+        };
+
+        if let Some(score) = score {
+            match best.get(&(parent, base)) {
+                Some((best_score, _)) if *best_score >= score => {}
+                _ => {
+                    best.insert((parent, base), (score, name));
+                }
+            }
+        }
+    }
+
+    let selected = |entry: &str| {
+        let mut prefix = entry;
+        loop {
+            if known.contains(prefix) {
+                let (parent, name) = split_parent(prefix);
+                match best.get(&(parent, split(name).0)) {
+                    Some((_, best_name)) if *best_name == name => {}
+                    _ => return false,
+                }
+            }
+
+            match prefix.rfind('/') {
+                Some(index) => prefix = &prefix[..index],
+                None => return true,
+            }
+        }
+    };
+
+    entries.iter().filter(|e| selected(e)).cloned().collect()
+}
+
+fn split(name: &str) -> (&str, Option<&str>) {
+    match name.split_once(SEPARATOR) {
+        Some((base, conditions)) if !base.is_empty() => (base, Some(conditions)),
+        _ => (name, None),
+    }
+}
+
+fn split_parent(file: &str) -> (&str, &str) {
+    file.rsplit_once('/').unwrap_or(("", file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Machine {
+        Machine {
+            os: "linux".to_string(),
+            host: "work-vm.corp".to_string(),
+            user: "jdoe".to_string(),
+            tags: vec!["work".to_string()],
+        }
+    }
+
+    #[test]
+    fn base_path() {
+        let testcases = vec![
+            ("zshrc", "zshrc"),
+            ("zshrc##os.linux", "zshrc"),
+            ("nvim##host.work-vm/init.lua##default", "nvim/init.lua"),
+            ("##os.linux", "##os.linux"),
+        ];
+
+        for (file, expected) in testcases {
+            assert_eq!(expected, base(file), "file {}", file)
+        }
+    }
+
+    #[test]
+    fn score_conditions() {
+        let testcases = vec![
+            ("default", Some(0)),
+            ("os.linux", Some(1)),
+            ("os.macos", None),
+            ("tag.work", Some(2)),
+            ("tag.personal", None),
+            ("user.jdoe", Some(4)),
+            ("host.work-vm", Some(8)),
+            ("host.work-vm.corp", Some(8)),
+            ("host.corp", None),
+            ("os.linux,tag.work", Some(3)),
+            ("os.linux,tag.personal", None),
+            ("linux", None),
+        ];
+
+        for (conditions, expected) in testcases {
+            assert_eq!(
+                expected,
+                machine().score(conditions),
+                "conditions {}",
+                conditions
+            )
+        }
+    }
+
+    #[test]
+    fn select_variants() {
+        let entries = vec![
+            "gitconfig",
+            "gitconfig##host.work-vm",
+            "nvim##os.macos",
+            "nvim##os.macos/init.lua",
+            "nvim##os.linux",
+            "nvim##os.linux/init.lua",
+            "nvim##os.linux/lua##tag.personal",
+            "zshenv##os.macos",
+            "zshrc##default",
+            "zshrc##os.linux",
+            "zshrc##os.linux,tag.work",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(
+            vec![
+                "gitconfig##host.work-vm",
+                "nvim##os.linux",
+                "nvim##os.linux/init.lua",
+                "zshrc##os.linux,tag.work",
+            ],
+            select(entries, &machine())
+        );
+    }
+
+    #[test]
+    fn select_subpath() {
+        let entries = vec!["nvim##os.linux/init.lua##default".to_string()];
+
+        assert_eq!(entries.clone(), select(entries, &machine()));
+    }
+}